@@ -0,0 +1,215 @@
+use std::time::{Duration, Instant};
+
+use serde::de::DeserializeOwned;
+
+use crate::config::Config;
+
+const DEFAULT_PANEL_URL: &str = "https://panel.simrail.eu:8084";
+const DEFAULT_TIMETABLE_URL: &str = "https://simrail-edr.emeraldnetwork.xyz";
+
+/// Characters of an unexpected response body written to the debug log.
+const SNIPPET_LEN: usize = 200;
+/// Wait before the first retry of a failed request, doubled on each retry.
+const RETRY_BACKOFF: Duration = Duration::from_millis(250);
+
+/// Endpoints of the SimRail panel and of the timetable service.
+pub struct Api {
+    panel: String,
+    timetable: String,
+    /// Retries of a request failing with a timeout or a server error.
+    retries: u32,
+    /// No retry starts once a request has been going on for this long, so
+    /// that a refresh does not outlast the refresh interval.
+    retry_budget: Duration,
+}
+
+impl Api {
+    /// Uses the URLs of the config, the official servers when unset.
+    pub fn new(config: &Config) -> Api {
+        let base = |configured: &Option<String>, default: &str| {
+            configured
+                .as_deref()
+                .unwrap_or(default)
+                .trim_end_matches('/')
+                .to_string()
+        };
+        Api {
+            panel: base(&config.panel_url, DEFAULT_PANEL_URL),
+            timetable: base(&config.timetable_url, DEFAULT_TIMETABLE_URL),
+            retries: config.request_retries,
+            retry_budget: config.refresh_interval(),
+        }
+    }
+
+    /// Fetches and decodes a JSON document, retrying with an exponential
+    /// backoff on timeouts and server errors.
+    pub async fn get_json<T: DeserializeOwned>(
+        &self,
+        client: &reqwest::Client,
+        url: String,
+    ) -> crate::Result<T> {
+        let started = Instant::now();
+        let mut backoff = RETRY_BACKOFF;
+        let mut attempt = 0;
+        loop {
+            match fetch_json(client, &url).await {
+                Ok(value) => return Ok(value),
+                Err(Failure::Transient(err))
+                    if attempt < self.retries
+                        && started.elapsed() + backoff < self.retry_budget =>
+                {
+                    attempt += 1;
+                    log::info!("GET {url}: retry {attempt} in {backoff:?} after: {err}");
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                Err(Failure::Transient(err) | Failure::Permanent(err)) => return Err(err),
+            }
+        }
+    }
+
+    pub fn servers_url(&self) -> String {
+        format!("{}/servers-open", self.panel)
+    }
+
+    pub fn stations_url(&self, server: &str) -> String {
+        format!("{}/stations-open?serverCode={}", self.panel, server)
+    }
+
+    pub fn trains_url(&self, server: &str) -> String {
+        format!("{}/trains-open?serverCode={}", self.panel, server)
+    }
+
+    pub fn users_url(&self, steam_ids: &[String]) -> String {
+        format!("{}/users-open/{}", self.panel, steam_ids.join(","))
+    }
+
+    pub fn timetable_url(&self, server: &str, train_no: &str) -> String {
+        format!("{}/train/{}/{}", self.timetable, server, train_no)
+    }
+}
+
+/// A failed request, only the transient failures are worth retrying.
+enum Failure {
+    Transient(crate::BoxError),
+    Permanent(crate::BoxError),
+}
+
+/// Fetches and decodes a JSON document, every request is written to the debug
+/// log with its status and latency.
+async fn fetch_json<T: DeserializeOwned>(
+    client: &reqwest::Client,
+    url: &str,
+) -> Result<T, Failure> {
+    let started = Instant::now();
+    let res = match client.get(url).send().await {
+        Ok(res) => res,
+        Err(err) => {
+            log::warn!("GET {url} failed after {:?}: {err}", started.elapsed());
+            return Err(Failure::Transient(err.into()));
+        }
+    };
+    let status = res.status();
+    log::debug!("GET {url} -> {status} in {:?}", started.elapsed());
+    let body = res
+        .text()
+        .await
+        .map_err(|err| Failure::Transient(err.into()))?;
+    // Error pages come back as HTML, only the start of them is worth logging.
+    let snippet = || body.chars().take(SNIPPET_LEN).collect::<String>();
+    if !status.is_success() {
+        log::warn!("GET {url} -> {status}: {}", snippet());
+        let err = format!("{url} answered {status}").into();
+        return Err(if status.is_server_error() {
+            Failure::Transient(err)
+        } else {
+            Failure::Permanent(err)
+        });
+    }
+    serde_json::from_str(&body).map_err(|err| {
+        log::warn!("GET {url}: invalid body ({err}): {}", snippet());
+        Failure::Permanent(format!("{url} did not answer valid JSON: {err}").into())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+    use wiremock::matchers::path;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+
+    fn api(server: &MockServer, retries: u32) -> Api {
+        Api::new(&Config {
+            panel_url: Some(server.uri()),
+            request_retries: retries,
+            ..Config::default()
+        })
+    }
+
+    /// Answers 500 to the first `failures` requests, then a valid document.
+    async fn flaky_server(failures: u64) -> MockServer {
+        let server = MockServer::start().await;
+        Mock::given(path("/servers-open"))
+            .respond_with(ResponseTemplate::new(500))
+            .up_to_n_times(failures)
+            .expect(failures)
+            .mount(&server)
+            .await;
+        Mock::given(path("/servers-open"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "ok": true })))
+            .mount(&server)
+            .await;
+        server
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_retried() {
+        let server = flaky_server(2).await;
+        let api = api(&server, 2);
+        let value: Value = api
+            .get_json(&reqwest::Client::new(), api.servers_url())
+            .await
+            .unwrap();
+        assert_eq!(value, json!({ "ok": true }));
+    }
+
+    #[tokio::test]
+    async fn retries_are_limited() {
+        let server = flaky_server(2).await;
+        let api = api(&server, 1);
+        let result = api
+            .get_json::<Value>(&reqwest::Client::new(), api.servers_url())
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn client_errors_are_not_retried() {
+        let server = MockServer::start().await;
+        Mock::given(path("/servers-open"))
+            .respond_with(ResponseTemplate::new(404))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let api = api(&server, 2);
+        let result = api
+            .get_json::<Value>(&reqwest::Client::new(), api.servers_url())
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn urls_come_from_the_config() {
+        let api = Api::new(&Config {
+            panel_url: Some(String::from("http://localhost:8084/")),
+            ..Config::default()
+        });
+        assert_eq!(api.servers_url(), "http://localhost:8084/servers-open");
+        assert_eq!(
+            api.timetable_url("pl1", "40150"),
+            format!("{DEFAULT_TIMETABLE_URL}/train/pl1/40150")
+        );
+    }
+}
@@ -0,0 +1,100 @@
+use crate::config::Config;
+use crate::export;
+use crate::persist::Session;
+use crate::state::{State, Step};
+
+const USAGE: &str =
+    "usage: simrail [--verbose] [--once [--json]] [--server <code> [--station <name>]]
+
+  --once              print the board of the station once and exit
+  --json              print the board as JSON instead of a table
+  --server <code>     server code, e.g. en1, to open instead of the last session
+  --station <name>    station name or code as listed in the station selection
+  --verbose           log every request and refresh error to debug.log in the data directory";
+
+/// Command line arguments, the TUI starts unless `--once` is given.
+#[derive(Debug, Default)]
+pub struct Args {
+    pub once: bool,
+    pub json: bool,
+    pub verbose: bool,
+    pub server: Option<String>,
+    pub station: Option<String>,
+}
+
+impl Args {
+    pub fn parse() -> crate::Result<Args> {
+        let mut args = Args::default();
+        let mut iter = std::env::args().skip(1);
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--once" => args.once = true,
+                "--json" => args.json = true,
+                "--verbose" => args.verbose = true,
+                "--server" => args.server = Some(iter.next().ok_or("--server needs a value")?),
+                "--station" => args.station = Some(iter.next().ok_or("--station needs a value")?),
+                "-h" | "--help" => {
+                    println!("{USAGE}");
+                    std::process::exit(0);
+                }
+                _ => return Err(format!("unknown argument {arg}\n{USAGE}").into()),
+            }
+        }
+        Ok(args)
+    }
+}
+
+/// Fetches the board of the requested station once and prints it as plain text.
+pub async fn once(config: Config, args: &Args) -> crate::Result<()> {
+    let (Some(server), Some(station)) = (args.server.clone(), args.station.clone()) else {
+        return Err(format!("--once needs --server and --station\n{USAGE}").into());
+    };
+
+    let mut state = State::new(config).await?;
+    state
+        .resume(Session {
+            server: Some(server.clone()),
+            station: Some(station.clone()),
+        })
+        .await;
+    if let Some(err) = state.refresh_error.take() {
+        return Err(err.into());
+    }
+    if !matches!(state.step, Step::EDR) {
+        return Err(format!("station {station} not found on active server {server}").into());
+    }
+    state.refresh_data().await?;
+    state.events.sort();
+
+    if args.json {
+        println!("{}", export::board_json(&state)?);
+        return Ok(());
+    }
+
+    // The columns of the board at full width.
+    let layout = state.board_layout();
+    let rows: Vec<Vec<String>> = std::iter::once(layout.shown(state.board_header()))
+        .chain(
+            state
+                .visible_events()
+                .into_iter()
+                .map(|e| layout.shown(state.board_columns(e))),
+        )
+        .collect();
+    let mut widths = vec![0; rows[0].len()];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    for row in &rows {
+        let line = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, &width)| format!("{cell:width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        println!("{}", line.trim_end());
+    }
+    Ok(())
+}
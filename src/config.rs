@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use chrono::{DateTime, Local, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use tui::style::Color;
+
+use crate::theme::ThemeName;
+
+const DEFAULT_REFRESH_SECS: f32 = 5.0;
+
+#[derive(Debug, Deserialize, Clone, Copy)]
+pub enum TimeFormat {
+    /// `14:05`
+    H24,
+    /// `02:05 PM`
+    H12,
+}
+
+/// Columns of the board, in the order of `state::BOARD_HEADER`.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    Marker,
+    Train,
+    Category,
+    Type,
+    /// Only shown on combined boards.
+    Station,
+    Time,
+    Delay,
+    /// Estimated arrival of approaching player trains.
+    Eta,
+    Platform,
+    Route,
+    From,
+    To,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Seconds between two data refreshes.
+    pub refresh_secs: f32,
+    /// Requests taking longer than this are abandoned and retried on the next refresh.
+    pub request_timeout_secs: u64,
+    /// Times a request failing with a timeout or a server error is retried.
+    pub request_retries: u32,
+    /// Steam id -> color used to highlight the stations dispatched by that player.
+    pub dispatcher_colors: HashMap<String, Color>,
+    /// Repeated navigation keys arriving faster than this are dropped, 0 disables it.
+    pub nav_debounce_ms: u64,
+    /// Whether moving past the last entry of a list goes back to the first
+    /// one and the other way around.
+    pub wrap_navigation: bool,
+    /// How long a platform reassignment stays highlighted on the board.
+    pub platform_change_secs: u64,
+    /// Train names longer than this are shortened with an ellipsis on the board.
+    pub train_name_max: usize,
+    /// Stations whose boards are kept warm as extra tabs next to the selected one.
+    pub monitored_stations: Vec<String>,
+    /// Trains farther than this from every station, in km, are not matched
+    /// to a station by position. 0 disables the limit.
+    pub max_station_distance_km: f32,
+    /// Minutes in the past still shown when the board is limited to a time
+    /// window, counted positive: 15 shows the events of the last 15 minutes.
+    pub window_before_mins: i64,
+    /// Minutes ahead shown when the board is limited to a time window.
+    pub window_after_mins: i64,
+    /// Seconds a status message stays in the footer.
+    pub status_secs: u64,
+    /// Events at least this many minutes late are flagged on the board.
+    pub delay_alert_mins: i64,
+    /// Color of the time and delay of flagged events.
+    pub delay_alert_color: Color,
+    /// Colors and text attributes of the screens.
+    pub theme: ThemeName,
+    /// Starts the board with separate scheduled and actual time columns
+    /// rather than the time and the delay.
+    pub split_times: bool,
+    /// Clock used to display the event times.
+    pub time_format: TimeFormat,
+    /// IANA name of the time zone times are shown in, e.g. "Europe/Warsaw".
+    /// The system time zone is used when unset.
+    pub timezone: Option<Tz>,
+    /// Rings the terminal bell when a train of the watchlist arrives.
+    pub notify_watched: bool,
+    /// Board columns to show, in this order.
+    pub columns: Vec<Column>,
+    /// Width and height below which only a request to enlarge the terminal is
+    /// shown.
+    pub min_size: (u16, u16),
+    /// Terminals narrower than this get a compact board with only the train,
+    /// time and delay columns.
+    pub compact_width: u16,
+    /// Base URL of the SimRail panel API, e.g. to go through a proxy.
+    /// `EDR_PANEL_URL` takes precedence.
+    pub panel_url: Option<String>,
+    /// Base URL of the timetable service, `EDR_TIMETABLE_URL` takes precedence.
+    pub timetable_url: Option<String>,
+    /// Accepts any TLS certificate, only for networks whose proxy re-signs
+    /// the traffic. Anyone on the path can then read and alter the data.
+    pub danger_accept_invalid_certs: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            refresh_secs: DEFAULT_REFRESH_SECS,
+            request_timeout_secs: 10,
+            request_retries: 2,
+            dispatcher_colors: HashMap::new(),
+            nav_debounce_ms: 40,
+            wrap_navigation: true,
+            platform_change_secs: 60,
+            train_name_max: 24,
+            monitored_stations: vec![],
+            max_station_distance_km: 15.0,
+            window_before_mins: 15,
+            window_after_mins: 60,
+            status_secs: 5,
+            delay_alert_mins: 10,
+            delay_alert_color: Color::LightRed,
+            theme: ThemeName::Default,
+            split_times: false,
+            time_format: TimeFormat::H24,
+            timezone: None,
+            notify_watched: false,
+            columns: vec![
+                Column::Marker,
+                Column::Train,
+                Column::Category,
+                Column::Type,
+                Column::Station,
+                Column::Time,
+                Column::Delay,
+                Column::Eta,
+                Column::Platform,
+                Column::Route,
+                Column::From,
+                Column::To,
+            ],
+            min_size: (40, 10),
+            compact_width: 100,
+            panel_url: None,
+            timetable_url: None,
+            danger_accept_invalid_certs: false,
+        }
+    }
+}
+
+impl Config {
+    /// Loads `<config dir>/edr/config.toml`, falling back to the defaults when the
+    /// file is missing or malformed. The `EDR_PANEL_URL` and `EDR_TIMETABLE_URL`
+    /// environment variables take precedence over the URLs of the file.
+    pub fn load() -> Config {
+        let mut config: Config = path()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|content| toml::from_str(&content).ok())
+            .unwrap_or_default();
+        for (var, url) in [
+            ("EDR_PANEL_URL", &mut config.panel_url),
+            ("EDR_TIMETABLE_URL", &mut config.timetable_url),
+        ] {
+            if let Ok(value) = std::env::var(var) {
+                *url = Some(value);
+            }
+        }
+        config
+    }
+
+    /// The refresh interval, invalid values fall back to the default instead of
+    /// turning the main loop into a busy loop.
+    pub fn refresh_interval(&self) -> Duration {
+        let secs = if self.refresh_secs.is_finite() && self.refresh_secs > 0.0 {
+            self.refresh_secs
+        } else {
+            DEFAULT_REFRESH_SECS
+        };
+        Duration::from_secs_f32(secs)
+    }
+
+    pub fn format_time(&self, time: &DateTime<Utc>) -> String {
+        let pattern = match self.time_format {
+            TimeFormat::H24 => "%H:%M",
+            TimeFormat::H12 => "%I:%M %p",
+        };
+        self.local_time(time).format(pattern).to_string()
+    }
+
+    /// `format_time` with the seconds, for the time of the last refresh.
+    pub fn format_time_secs(&self, time: &DateTime<Utc>) -> String {
+        let pattern = match self.time_format {
+            TimeFormat::H24 => "%H:%M:%S",
+            TimeFormat::H12 => "%I:%M:%S %p",
+        };
+        self.local_time(time).format(pattern).to_string()
+    }
+
+    /// Wall clock time of the configured time zone, only meant for display:
+    /// durations such as delays are computed on the UTC times.
+    pub fn local_time(&self, time: &DateTime<Utc>) -> NaiveDateTime {
+        match self.timezone {
+            Some(tz) => time.with_timezone(&tz).naive_local(),
+            None => time.with_timezone(&Local).naive_local(),
+        }
+    }
+
+    /// Width of the times produced by `format_time`.
+    pub fn time_width(&self) -> u16 {
+        match self.time_format {
+            TimeFormat::H24 => 5,
+            TimeFormat::H12 => 8,
+        }
+    }
+
+    pub fn dispatcher_color<'a, I>(&self, steam_ids: I) -> Option<Color>
+    where
+        I: IntoIterator<Item = &'a String>,
+    {
+        steam_ids
+            .into_iter()
+            .find_map(|id| self.dispatcher_colors.get(id).copied())
+    }
+}
+
+fn path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("edr").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_a_toml_file() {
+        let config: Config = toml::from_str(
+            r#"
+            refresh_secs = 2.5
+            time_format = "H12"
+            timezone = "Europe/Warsaw"
+            theme = "Mono"
+            columns = ["Marker", "Train", "Time"]
+            min_size = [60, 20]
+
+            [dispatcher_colors]
+            76561198000000000 = "LightBlue"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.refresh_interval(), Duration::from_secs_f32(2.5));
+        assert_eq!(config.timezone, Some(chrono_tz::Europe::Warsaw));
+        assert_eq!(
+            config.columns,
+            [Column::Marker, Column::Train, Column::Time]
+        );
+        assert_eq!(config.min_size, (60, 20));
+        assert_eq!(
+            config.dispatcher_color([&String::from("76561198000000000")]),
+            Some(Color::LightBlue)
+        );
+        // Unset keys keep their defaults.
+        assert_eq!(config.compact_width, Config::default().compact_width);
+    }
+
+    #[test]
+    fn invalid_refresh_intervals_fall_back_to_the_default() {
+        for refresh_secs in [0.0, -1.0, f32::NAN] {
+            let config = Config {
+                refresh_secs,
+                ..Config::default()
+            };
+            assert_eq!(
+                config.refresh_interval(),
+                Duration::from_secs_f32(DEFAULT_REFRESH_SECS)
+            );
+        }
+    }
+
+    #[test]
+    fn formats_the_same_time_on_both_clocks() {
+        let time: DateTime<Utc> = "2024-05-01T14:05:09Z".parse().unwrap();
+        let config = |time_format| Config {
+            time_format,
+            timezone: Some(chrono_tz::UTC),
+            ..Config::default()
+        };
+        assert_eq!(config(TimeFormat::H24).format_time(&time), "14:05");
+        assert_eq!(config(TimeFormat::H12).format_time(&time), "02:05 PM");
+        assert_eq!(config(TimeFormat::H24).format_time_secs(&time), "14:05:09");
+        assert_eq!(
+            config(TimeFormat::H12).format_time_secs(&time),
+            "02:05:09 PM"
+        );
+        assert_eq!(config(TimeFormat::H24).time_width(), 5);
+        assert_eq!(config(TimeFormat::H12).time_width(), 8);
+    }
+}
@@ -1,8 +1,10 @@
 use chrono::{DateTime, Utc};
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
 
 #[derive(Debug, Deserialize)]
 pub struct ServerResponse {
+    #[allow(dead_code)]
     pub result: bool,
     pub data: Vec<Server>,
 }
@@ -17,8 +19,22 @@ pub struct Server {
     pub is_active: bool,
 }
 
+impl Server {
+    /// Region the code starts with in uppercase, e.g. "EN" for "en1". Codes
+    /// without a leading letter are in no region.
+    pub fn region(&self) -> Option<String> {
+        let region: String = self
+            .server_code
+            .chars()
+            .take_while(|c| c.is_ascii_alphabetic())
+            .collect();
+        (!region.is_empty()).then(|| region.to_uppercase())
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct StationResponse {
+    #[allow(dead_code)]
     pub result: bool,
     pub data: Vec<Station>,
 }
@@ -31,12 +47,23 @@ pub struct Station {
     pub prefix: String,
     #[serde(rename(deserialize = "DispatchedBy"))]
     pub dispatched_by: Vec<Player>,
+    // The misspelling is the API's, unlike the one of the train coordinates.
     #[serde(rename(deserialize = "Latititude"))]
     pub latitude: f32,
     #[serde(rename(deserialize = "Longitude"))]
     pub longitude: f32,
 }
 
+impl Station {
+    /// Stations reported at (0, 0) have no real coordinates and must not take
+    /// part in the nearest station lookup.
+    pub(crate) fn has_position(&self) -> bool {
+        self.latitude.is_finite()
+            && self.longitude.is_finite()
+            && (self.latitude, self.longitude) != (0.0, 0.0)
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Player {
     #[serde(rename(deserialize = "SteamId"))]
@@ -64,6 +91,7 @@ pub struct SteamInfo {
 
 #[derive(Debug, Deserialize)]
 pub struct TrainResponse {
+    #[allow(dead_code)]
     pub result: bool,
     pub data: Vec<Train>,
 }
@@ -88,29 +116,88 @@ pub struct Train {
     pub loc: Option<String>,
 }
 impl Train {
+    /// Trains at (0, 0) or at non-finite coordinates come from incomplete
+    /// data and would be matched to a bogus nearest station.
+    pub(crate) fn has_position(&self) -> bool {
+        self.train_data.latitude.is_finite()
+            && self.train_data.longitude.is_finite()
+            && (self.train_data.latitude, self.train_data.longitude) != (0.0, 0.0)
+    }
+
+    /// `index_of_point` of the timetable entry the train is at, negative
+    /// indexes mean the simulation does not know.
+    pub(crate) fn timetable_index(&self) -> Option<u64> {
+        u64::try_from(self.train_data.vddelayed_timetable_index).ok()
+    }
+
+    /// Live estimate of the time left to reach `station`, as the crow flies
+    /// at the current speed.
+    pub(crate) fn eta_to(&self, station: &Station) -> Option<chrono::Duration> {
+        if !self.has_position() || !station.has_position() {
+            return None;
+        }
+        eta(self.dist_from(station), self.train_data.velocity)
+    }
+
     pub(crate) fn dist_from(&self, station: &Station) -> f32 {
-        const R: f32 = 6371.;
+        distance_km(
+            self.train_data.latitude,
+            self.train_data.longitude,
+            station.latitude,
+            station.longitude,
+        )
+    }
+}
 
-        let lat_a = self.train_data.latitude.to_radians();
-        let lat_b = station.latitude.to_radians();
+/// Great-circle distance between two positions given in degrees.
+pub(crate) fn distance_km(lat_a: f32, lon_a: f32, lat_b: f32, lon_b: f32) -> f32 {
+    const R: f32 = 6371.;
 
-        let d_lat = (self.train_data.latitude - station.latitude).to_radians();
-        let d_lon = (self.train_data.longitude - station.longitude).to_radians();
+    let d_lat = (lat_a - lat_b).to_radians();
+    let d_lon = (lon_a - lon_b).to_radians();
+    let lat_a = lat_a.to_radians();
+    let lat_b = lat_b.to_radians();
 
-        let a =
-            (d_lat / 2.0).sin().powi(2) + lat_a.cos() * lat_b.cos() * (d_lon / 2.0).sin().powi(2);
+    let a = (d_lat / 2.0).sin().powi(2) + lat_a.cos() * lat_b.cos() * (d_lon / 2.0).sin().powi(2);
+    // Rounding can push `a` slightly above 1 for antipodal points, where the
+    // `asin` form would yield NaN.
+    let a = a.clamp(0.0, 1.0);
+
+    R * (2.0 * a.sqrt().atan2((1.0 - a).sqrt()))
+}
 
-        R * (2.0 * a.sqrt().asin())
+/// Time left to cover `distance_km` at `velocity_kmh`, `None` for a train
+/// that is (almost) stopped.
+///
+/// This assumes the train keeps its current speed all the way, so it runs
+/// early for trains about to brake and late for trains speeding up.
+pub(crate) fn eta(distance_km: f32, velocity_kmh: f32) -> Option<chrono::Duration> {
+    const MIN_VELOCITY_KMH: f32 = 1.0;
+
+    if !distance_km.is_finite() || velocity_kmh.is_nan() || velocity_kmh < MIN_VELOCITY_KMH {
+        return None;
     }
+    let secs = distance_km / velocity_kmh * 3600.0;
+    Some(chrono::Duration::seconds(secs.round() as i64))
 }
+
 #[derive(Debug, Deserialize)]
 pub struct TrainData {
     #[serde(rename(deserialize = "ControlledBySteamID"))]
     pub controlled_by_steam_id: Option<String>,
 
-    #[serde(rename(deserialize = "Latititute"))]
+    // Both names are misspelled this way by the API.
+    #[serde(
+        rename(deserialize = "Latititute"),
+        deserialize_with = "lenient_coordinate",
+        default = "missing_coordinate"
+    )]
     pub latitude: f32,
-    #[serde(rename(deserialize = "Longitute"))]
+    #[serde(
+        rename(deserialize = "Longitute"),
+        deserialize_with = "lenient_coordinate",
+        default = "missing_coordinate"
+    )]
     pub longitude: f32,
 
     #[serde(rename(deserialize = "SignalInFront"))]
@@ -126,22 +213,374 @@ pub struct TrainData {
 
 #[derive(Debug, Deserialize)]
 pub struct StopDescription {
-    pub nameOfPoint: String,
+    #[serde(rename(deserialize = "nameOfPoint"))]
+    pub name_of_point: String,
     pub line: i64,
-    pub arrivalTime: Option<String>,
-    pub departureTime: Option<String>,
-    pub actualArrivalTime: Option<String>,
-    pub actualDepartureTime: Option<String>,
+    #[serde(rename(deserialize = "arrivalTime"))]
+    pub arrival_time: Option<String>,
+    #[serde(rename(deserialize = "departureTime"))]
+    pub departure_time: Option<String>,
+    // The actual times are only reported once the train got there, anything
+    // but a usable value reads as not reported rather than failing the whole
+    // timetable.
+    #[serde(
+        rename(deserialize = "actualArrivalTime"),
+        deserialize_with = "lenient_text",
+        default
+    )]
+    pub actual_arrival_time: Option<String>,
+    #[serde(
+        rename(deserialize = "actualDepartureTime"),
+        deserialize_with = "lenient_text",
+        default
+    )]
+    pub actual_departure_time: Option<String>,
 
-    pub actualArrivalObject: DateTime<Utc>,
-    pub actualDepartureObject: DateTime<Utc>,
-    pub scheduledArrivalObject: DateTime<Utc>,
-    pub scheduledDepartureObject: DateTime<Utc>,
+    #[serde(
+        rename(deserialize = "actualArrivalObject"),
+        deserialize_with = "lenient_time",
+        default
+    )]
+    pub actual_arrival_object: Option<DateTime<Utc>>,
+    #[serde(
+        rename(deserialize = "actualDepartureObject"),
+        deserialize_with = "lenient_time",
+        default
+    )]
+    pub actual_departure_object: Option<DateTime<Utc>>,
+    #[serde(rename(deserialize = "scheduledArrivalObject"))]
+    pub scheduled_arrival_object: DateTime<Utc>,
+    #[serde(rename(deserialize = "scheduledDepartureObject"))]
+    pub scheduled_departure_object: DateTime<Utc>,
 
     pub platform: Option<String>,
     pub track: Option<i64>,
 
-    pub plannedStop: Option<i64>,
+    #[serde(rename(deserialize = "plannedStop"))]
+    pub planned_stop: Option<i64>,
+
+    #[serde(rename(deserialize = "indexOfPoint"))]
+    pub index_of_point: u64,
+}
+
+impl StopDescription {
+    /// Actual arrival time, once the train reported one.
+    pub fn actual_arrival(&self) -> Option<DateTime<Utc>> {
+        self.actual_arrival_time
+            .as_ref()
+            .and(self.actual_arrival_object)
+    }
+
+    /// Actual departure time, once the train reported one.
+    pub fn actual_departure(&self) -> Option<DateTime<Utc>> {
+        self.actual_departure_time
+            .as_ref()
+            .and(self.actual_departure_object)
+    }
+}
+
+/// The API occasionally sends `null` or strings for train coordinates. Those
+/// are read as NaN instead of failing the whole train list.
+fn lenient_coordinate<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    Ok(match Value::deserialize(deserializer)? {
+        Value::Number(n) => n.as_f64().map_or(f32::NAN, |n| n as f32),
+        Value::String(s) => s.trim().parse().unwrap_or(f32::NAN),
+        _ => f32::NAN,
+    })
+}
+
+fn lenient_text<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    Ok(match Value::deserialize(deserializer)? {
+        Value::String(s) if !s.trim().is_empty() => Some(s),
+        _ => None,
+    })
+}
+
+fn lenient_time<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<DateTime<Utc>>, D::Error> {
+    Ok(match Value::deserialize(deserializer)? {
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    })
+}
+
+fn missing_coordinate() -> f32 {
+    f32::NAN
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Shape of a `trains-open` answer, one train with unusable coordinates.
+    const TRAINS: &str = r#"{
+        "result": true,
+        "count": 2,
+        "description": "Data for trains on server pl1",
+        "data": [
+            {
+                "TrainNoLocal": "40150",
+                "TrainName": "EIP",
+                "StartStation": "Warszawa Wschodnia",
+                "EndStation": "Kraków Główny",
+                "Vehicles": ["Pendolino/ED250-018 Variant"],
+                "ServerCode": "pl1",
+                "TrainData": {
+                    "ControlledBySteamID": null,
+                    "InBorderStationArea": false,
+                    "Latititute": 50.2587,
+                    "Longitute": 19.0172,
+                    "Velocity": 112.5,
+                    "SignalInFront": "KO_D@7129,0,1",
+                    "DistanceToSignalInFront": 1312.4,
+                    "VDDelayedTimetableIndex": 27
+                },
+                "id": "662d4dfd4bd6a4f0e89e9e0f",
+                "Type": "bot"
+            },
+            {
+                "TrainNoLocal": "14102",
+                "TrainName": "ROJ",
+                "StartStation": "Katowice",
+                "EndStation": "Sosnowiec Główny",
+                "Vehicles": ["EN57/EN57-1003"],
+                "ServerCode": "pl1",
+                "TrainData": {
+                    "ControlledBySteamID": "76561198000000000",
+                    "InBorderStationArea": true,
+                    "Latititute": null,
+                    "Longitute": "n/a",
+                    "Velocity": 0.0,
+                    "SignalInFront": null,
+                    "DistanceToSignalInFront": 0.0,
+                    "VDDelayedTimetableIndex": -1
+                },
+                "id": "662d4dfd4bd6a4f0e89e9e10",
+                "Type": "user"
+            }
+        ]
+    }"#;
+
+    /// Two stops of a timetable answer, the first one already passed.
+    const TIMETABLE: &str = r#"[
+        {
+            "indexOfPoint": 12,
+            "nameOfPoint": "Katowice",
+            "nameForPerson": "Katowice",
+            "pointId": "1234",
+            "displayedTrainNumber": "40150",
+            "arrivalTime": "2024-05-01 10:00:00",
+            "departureTime": "2024-05-01 10:02:00",
+            "actualArrivalTime": "2024-05-01 10:03:00",
+            "actualDepartureTime": "",
+            "scheduledArrivalObject": "2024-05-01T10:00:00.000Z",
+            "scheduledDepartureObject": "2024-05-01T10:02:00.000Z",
+            "actualArrivalObject": "2024-05-01T10:03:00.000Z",
+            "actualDepartureObject": null,
+            "stopType": "CommercialStop",
+            "line": 1,
+            "platform": "II",
+            "track": 2,
+            "plannedStop": 1,
+            "mileage": 300.5
+        },
+        {
+            "indexOfPoint": 13,
+            "nameOfPoint": "Sosnowiec Główny",
+            "nameForPerson": "Sosnowiec Główny",
+            "pointId": "1235",
+            "displayedTrainNumber": "40150",
+            "arrivalTime": "2024-05-01 10:09:00",
+            "departureTime": "2024-05-01 10:09:00",
+            "scheduledArrivalObject": "2024-05-01T10:09:00.000Z",
+            "scheduledDepartureObject": "2024-05-01T10:09:00.000Z",
+            "stopType": "NoStopOver",
+            "line": 133,
+            "platform": null,
+            "track": null,
+            "plannedStop": 0,
+            "mileage": 308.1
+        }
+    ]"#;
+
+    /// Shape of a `stations-open` answer.
+    const STATIONS: &str = r#"{
+        "result": true,
+        "count": 1,
+        "description": "Data for stations on server pl1",
+        "data": [
+            {
+                "Name": "Katowice",
+                "Prefix": "KO",
+                "DifficultyLevel": 5,
+                "Latititude": 50.2587,
+                "Longitude": 19.0172,
+                "MainImageURL": "https://api.simrail.eu:8083/Thumbnails/Stations/ko1m.jpg",
+                "AdditionalImage1URL": "https://api.simrail.eu:8083/Thumbnails/Stations/ko2.jpg",
+                "AdditionalImage2URL": "https://api.simrail.eu:8083/Thumbnails/Stations/ko3.jpg",
+                "DispatchedBy": [{ "ServerCode": "pl1", "SteamId": "76561198000000001" }],
+                "id": "644fc7ad2f1ba7b3b0c1d2e3"
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn stations_read_the_coordinates_under_the_api_spellings() {
+        let stations = serde_json::from_str::<StationResponse>(STATIONS)
+            .unwrap()
+            .data;
+        let station = &stations[0];
+        assert_eq!(
+            (station.name.as_str(), station.prefix.as_str()),
+            ("Katowice", "KO")
+        );
+        assert_eq!((station.latitude, station.longitude), (50.2587, 19.0172));
+        assert!(station.has_position());
+        assert_eq!(station.dispatched_by[0].steam_id, "76561198000000001");
+    }
+
+    #[test]
+    fn stations_without_the_api_latitude_key_are_rejected() {
+        // A key spelled differently must fail loudly rather than read as 0.0.
+        let payload = STATIONS.replace("Latititude", "Latitude");
+        assert!(serde_json::from_str::<StationResponse>(&payload).is_err());
+    }
+
+    #[test]
+    fn timetable_fields_match_the_payload() {
+        let stops: Vec<StopDescription> = serde_json::from_str(TIMETABLE).unwrap();
+        let raw: Vec<Value> = serde_json::from_str(TIMETABLE).unwrap();
+        for (stop, raw) in stops.iter().zip(&raw) {
+            assert_eq!(stop.name_of_point, raw["nameOfPoint"]);
+            assert_eq!(stop.index_of_point, raw["indexOfPoint"]);
+            assert_eq!(stop.line, raw["line"]);
+            assert_eq!(stop.planned_stop, raw["plannedStop"].as_i64());
+            assert_eq!(stop.platform.as_deref(), raw["platform"].as_str());
+            assert_eq!(stop.track, raw["track"].as_i64());
+            assert_eq!(stop.arrival_time.as_deref(), raw["arrivalTime"].as_str());
+            assert_eq!(
+                stop.departure_time.as_deref(),
+                raw["departureTime"].as_str()
+            );
+            let time = |key: &str| raw[key].as_str().unwrap().parse::<DateTime<Utc>>().unwrap();
+            assert_eq!(
+                stop.scheduled_arrival_object,
+                time("scheduledArrivalObject")
+            );
+            assert_eq!(
+                stop.scheduled_departure_object,
+                time("scheduledDepartureObject")
+            );
+        }
+
+        let passed = &stops[0];
+        assert_eq!(
+            passed.actual_arrival(),
+            Some("2024-05-01T10:03:00Z".parse().unwrap())
+        );
+        assert_eq!(passed.actual_departure(), None);
+        assert_eq!(stops[1].actual_arrival(), None);
+    }
+
+    #[test]
+    fn malformed_coordinates_do_not_fail_the_train_list() {
+        let trains = serde_json::from_str::<TrainResponse>(TRAINS).unwrap().data;
+        assert_eq!(trains.len(), 2);
+        assert!(trains[0].has_position());
+        assert_eq!(trains[0].train_data.latitude, 50.2587);
+        assert!(trains[1].train_data.latitude.is_nan());
+        assert!(trains[1].train_data.longitude.is_nan());
+        assert!(!trains[1].has_position());
+        assert_eq!(trains[1].timetable_index(), None);
+    }
 
-    pub indexOfPoint: u64,
+    #[test]
+    fn distances_between_known_places() {
+        let cases = [
+            // Katowice - Sosnowiec Główny
+            ((50.2587, 19.0172), (50.2776, 19.1288), 8.2),
+            // Warszawa Centralna - Kraków Główny
+            ((52.2289, 21.0034), (50.0677, 19.9476), 252.0),
+            ((50.0, 19.0), (50.0, 19.0), 0.0),
+        ];
+        for ((lat_a, lon_a), (lat_b, lon_b), km) in cases {
+            let distance = distance_km(lat_a, lon_a, lat_b, lon_b);
+            assert!(
+                (distance - km).abs() < km * 0.01 + 0.01,
+                "{distance} != {km}"
+            );
+            assert_eq!(distance, distance_km(lat_b, lon_b, lat_a, lon_a));
+        }
+    }
+
+    #[test]
+    fn antipodal_points_are_half_the_circumference_apart() {
+        let distance = distance_km(50.0, 19.0, -50.0, -161.0);
+        assert!(
+            (distance - 6371.0 * std::f32::consts::PI).abs() < 1.0,
+            "{distance}"
+        );
+    }
+
+    #[derive(Deserialize)]
+    struct Actual {
+        #[serde(deserialize_with = "lenient_text", default)]
+        text: Option<String>,
+        #[serde(deserialize_with = "lenient_time", default)]
+        time: Option<DateTime<Utc>>,
+    }
+
+    fn actual(value: &str) -> Actual {
+        serde_json::from_str(&format!(r#"{{ "text": {value}, "time": {value} }}"#)).unwrap()
+    }
+
+    #[test]
+    fn unusable_actual_times_read_as_not_reported() {
+        for value in [r#""""#, r#""  ""#, "null", "42", "[]"] {
+            let actual = actual(value);
+            assert_eq!((actual.text, actual.time), (None, None), "{value}");
+        }
+        // Garbage text is still text, only the time has to parse.
+        let garbage = actual(r#""soon""#);
+        assert_eq!(garbage.text.as_deref(), Some("soon"));
+        assert_eq!(garbage.time, None);
+
+        let missing: Actual = serde_json::from_str("{}").unwrap();
+        assert_eq!((missing.text, missing.time), (None, None));
+    }
+
+    #[test]
+    fn actual_times_are_read() {
+        let time = "2024-05-01T10:03:00Z".parse::<DateTime<Utc>>().unwrap();
+        let exact = actual(r#""2024-05-01T10:03:00Z""#);
+        assert_eq!(exact.text.as_deref(), Some("2024-05-01T10:03:00Z"));
+        assert_eq!(exact.time, Some(time));
+        let padded = actual(r#"" 2024-05-01T10:03:00.000Z ""#);
+        assert_eq!(padded.time, Some(time));
+    }
+
+    #[test]
+    fn eta_assumes_the_current_speed() {
+        assert_eq!(eta(10.0, 60.0), Some(chrono::Duration::seconds(600)));
+        assert_eq!(eta(0.0, 90.0), Some(chrono::Duration::zero()));
+    }
+
+    #[test]
+    fn no_eta_for_stopped_trains_or_unusable_values() {
+        for (distance, velocity) in [
+            (10.0, 0.0),
+            (10.0, 0.5),
+            (10.0, -30.0),
+            (10.0, f32::NAN),
+            (f32::NAN, 60.0),
+            (f32::INFINITY, 60.0),
+        ] {
+            assert_eq!(
+                eta(distance, velocity),
+                None,
+                "{distance} km at {velocity} km/h"
+            );
+        }
+    }
 }
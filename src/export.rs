@@ -0,0 +1,87 @@
+use std::path::PathBuf;
+
+use chrono::Utc;
+use serde::Serialize;
+
+use crate::state::{Event, State};
+
+#[derive(Serialize)]
+struct JsonEvent<'a> {
+    #[serde(flatten)]
+    event: &'a Event,
+    delay_minutes: Option<i64>,
+}
+
+/// Writes the events currently shown on the board to a timestamped CSV file in
+/// the working directory, with the columns of the screen.
+pub fn csv(state: &State) -> crate::Result<PathBuf> {
+    let layout = state.board_layout();
+    let mut content = csv_line(layout.shown(state.board_header()));
+    for event in state.visible_events() {
+        content.push_str(&csv_line(layout.shown(state.board_columns(event))));
+    }
+
+    let path = PathBuf::from(format!("{}.csv", file_stem(state)));
+    std::fs::write(&path, content)?;
+    Ok(path)
+}
+
+/// Writes the events currently shown on the board to a timestamped JSON file in
+/// the working directory. Unlike the CSV, every field of the events is written
+/// whatever the columns of the screen.
+pub fn json(state: &State) -> crate::Result<PathBuf> {
+    let path = PathBuf::from(format!("{}.json", file_stem(state)));
+    std::fs::write(&path, board_json(state)?)?;
+    Ok(path)
+}
+
+pub fn board_json(state: &State) -> crate::Result<String> {
+    let events: Vec<JsonEvent> = state
+        .visible_events()
+        .into_iter()
+        .map(|event| JsonEvent {
+            event,
+            delay_minutes: event.delay_minutes(),
+        })
+        .collect();
+    Ok(serde_json::to_string_pretty(&events)?)
+}
+
+fn file_stem(state: &State) -> String {
+    let station = state
+        .selected_station
+        .as_ref()
+        .map(|s| s.name.as_str())
+        .unwrap_or_default();
+    format!(
+        "edr-{}-{}-{}",
+        state.selected_server,
+        station,
+        Utc::now().format("%Y%m%d-%H%M%S")
+    )
+    .chars()
+    .map(|c| {
+        if c.is_alphanumeric() || c == '-' {
+            c
+        } else {
+            '_'
+        }
+    })
+    .collect()
+}
+
+fn csv_line<I: IntoIterator<Item = String>>(fields: I) -> String {
+    let mut line = fields
+        .into_iter()
+        .map(|field| {
+            if field.contains([',', '"', '\n']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    line.push('\n');
+    line
+}
@@ -0,0 +1,141 @@
+use crate::data::{distance_km, Station};
+
+/// Half size of the box searched around a train before falling back to all
+/// stations, in degrees of latitude.
+const BOX_DEG: f32 = 0.2;
+/// Kilometres per degree of latitude.
+const KM_PER_DEG: f32 = 111.2;
+
+/// Station positions prepared once per station list for the nearest station
+/// lookup of every train on every refresh.
+#[derive(Default)]
+pub struct StationLocator {
+    stations: Vec<(f32, f32, String)>,
+    /// Trains farther than this from every station are not located, 0 lifts
+    /// the limit.
+    max_km: f32,
+}
+
+impl StationLocator {
+    pub fn new(stations: &[Station], max_km: f32) -> StationLocator {
+        StationLocator {
+            max_km,
+            stations: stations
+                .iter()
+                .filter(|s| s.has_position())
+                .map(|s| (s.latitude, s.longitude, s.name.clone()))
+                .collect(),
+        }
+    }
+
+    /// Name of the station closest to the position. Only the stations in a box
+    /// around it are measured, unless the closest of them could be beaten by
+    /// one outside the box.
+    pub fn nearest(&self, latitude: f32, longitude: f32) -> Option<&str> {
+        let lon_deg = BOX_DEG / latitude.to_radians().cos().max(0.01);
+        let boxed = self.closest(latitude, longitude, |(lat, lon, _)| {
+            (lat - latitude).abs() <= BOX_DEG && (lon - longitude).abs() <= lon_deg
+        });
+        let (name, dist) = match boxed {
+            Some(closest) if closest.1 <= BOX_DEG * KM_PER_DEG * 0.9 => closest,
+            _ => self.closest(latitude, longitude, |_| true)?,
+        };
+        (self.max_km <= 0.0 || dist <= self.max_km).then_some(name)
+    }
+
+    fn closest<F>(&self, latitude: f32, longitude: f32, keep: F) -> Option<(&str, f32)>
+    where
+        F: Fn(&&(f32, f32, String)) -> bool,
+    {
+        self.stations
+            .iter()
+            .filter(keep)
+            .map(|(lat, lon, name)| (name.as_str(), distance_km(latitude, longitude, *lat, *lon)))
+            // Equally distant stations are told apart by name so that the
+            // result does not depend on the order of the station list.
+            .min_by(|a, b| a.1.total_cmp(&b.1).then_with(|| a.0.cmp(b.0)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn station(name: &str, latitude: f32, longitude: f32) -> Station {
+        Station {
+            name: name.to_string(),
+            prefix: String::new(),
+            dispatched_by: vec![],
+            latitude,
+            longitude,
+        }
+    }
+
+    /// Stations and positions spread over Poland and a bit beyond, from a
+    /// fixed seed.
+    fn positions(count: usize, seed: u64) -> Vec<(f32, f32)> {
+        let mut state = seed;
+        let mut next = move || {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 40) as f32 / (1u64 << 24) as f32
+        };
+        (0..count)
+            .map(|_| (48.0 + next() * 8.0, 13.0 + next() * 12.0))
+            .collect()
+    }
+
+    #[test]
+    fn nearest_matches_a_brute_force_search() {
+        let stations: Vec<Station> = positions(150, 1)
+            .into_iter()
+            .enumerate()
+            .map(|(i, (lat, lon))| station(&format!("S{i}"), lat, lon))
+            .collect();
+        let locator = StationLocator::new(&stations, 0.0);
+
+        for (lat, lon) in positions(1000, 2) {
+            let brute_force = stations
+                .iter()
+                .map(|s| {
+                    (
+                        s.name.as_str(),
+                        distance_km(lat, lon, s.latitude, s.longitude),
+                    )
+                })
+                .min_by(|a, b| a.1.total_cmp(&b.1).then_with(|| a.0.cmp(b.0)))
+                .map(|(name, _)| name);
+            assert_eq!(locator.nearest(lat, lon), brute_force, "at {lat}, {lon}");
+        }
+    }
+
+    #[test]
+    fn equally_distant_stations_are_told_apart_by_name() {
+        let east = station("Alpha", 50.0, 19.1);
+        let west = station("Beta", 50.0, 18.9);
+        assert_eq!(
+            distance_km(50.0, 19.0, 50.0, 19.1),
+            distance_km(50.0, 19.0, 50.0, 18.9)
+        );
+        for stations in [[east.clone(), west.clone()], [west, east]] {
+            let locator = StationLocator::new(&stations, 0.0);
+            assert_eq!(locator.nearest(50.0, 19.0), Some("Alpha"));
+        }
+    }
+
+    #[test]
+    fn trains_beyond_max_km_are_not_located() {
+        // About 36 km east of the station.
+        let stations = [station("Katowice", 50.0, 19.0)];
+        let position = (50.0, 19.5);
+        for (max_km, located) in [
+            (30.0, None),
+            (40.0, Some("Katowice")),
+            (0.0, Some("Katowice")),
+        ] {
+            let locator = StationLocator::new(&stations, max_km);
+            assert_eq!(locator.nearest(position.0, position.1), located, "{max_km}");
+        }
+    }
+}
@@ -1,90 +1,279 @@
-#![feature(async_closure)]
 use core::fmt::Display;
 use core::time::Duration;
 use std::process;
 use std::time::Instant;
 
-use crossterm::event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode};
+use chrono::{DateTime, Utc};
+use config::Config;
+use crossterm::event::{
+    self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyModifiers,
+};
 use crossterm::execute;
 use crossterm::terminal::{
     disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
 };
 use state::State;
+use theme::Theme;
 use tui::backend::{Backend, CrosstermBackend};
-use tui::layout::Constraint;
-use tui::style::{Color, Modifier, Style};
-use tui::widgets::{Block, Borders, List, ListItem, ListState, Row, Table};
+use tui::layout::{Constraint, Layout, Rect};
+use tui::style::Style;
+use tui::text::{Span, Spans, Text};
+use tui::widgets::{
+    Block, Borders, Cell, Clear, List, ListItem, ListState, Paragraph, Row, Table, TableState,
+    Tabs, Wrap,
+};
 use tui::{Frame, Terminal};
 
-pub type Result<T> = core::result::Result<T, Box<dyn std::error::Error>>;
+/// Refreshes taking longer than this turn the health indicator yellow.
+const SLOW_REFRESH: Duration = Duration::from_secs(2);
 
+pub type BoxError = Box<dyn std::error::Error>;
+pub type Result<T> = core::result::Result<T, BoxError>;
+
+mod api;
+mod cli;
+mod config;
 mod data;
+mod export;
+mod locator;
+mod persist;
 mod state;
+mod theme;
 
 macro_rules! exit_on_error {
-    ($to_test:expr,$terminal:expr) => {
+    ($to_test:expr,$terminal:expr,$state:expr) => {
         if let Err(err) = $to_test {
-            exit(&mut $terminal, Some(err))?;
+            exit(&mut $terminal, &$state, Some(err))?;
         }
     };
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let args = match cli::Args::parse() {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{err}");
+            process::exit(2);
+        }
+    };
+    if args.verbose {
+        if let Err(err) = persist::init_log() {
+            eprintln!("could not open the debug log: {err}");
+            process::exit(1);
+        }
+    }
+    if args.once {
+        if let Err(err) = cli::once(Config::load(), &args).await {
+            eprintln!("{err}");
+            process::exit(1);
+        }
+        return Ok(());
+    }
+
+    // Fetched before entering raw mode so that a failure leaves the terminal
+    // untouched.
+    let mut state = State::new(Config::load()).await?;
+    // A server or station given on the command line replaces the last session.
+    let session = if args.server.is_some() {
+        persist::Session {
+            server: args.server.clone(),
+            station: args.station.clone(),
+        }
+    } else {
+        persist::load(persist::SESSION_FILE)
+    };
+    state.resume(session).await;
+    state.apply_filters(persist::load(persist::FILTERS_FILE));
+
     enable_raw_mode()?;
     let mut stdout = std::io::stdout();
     crossterm::execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    let refresh_data = Duration::from_secs_f32(5.0);
+    let refresh_data = state.config.refresh_interval();
     let mut last_tick = Instant::now();
+    // Consecutive failed refreshes, for the debug log.
+    let mut failures = 0;
 
-    let mut state = State::new().await?;
+    let mut nav_debounce = NavDebounce::new(state.config.nav_debounce_ms);
 
-    let mut need_refresh_data = false;
+    let mut need_refresh_data = !matches!(state.step, state::Step::ServerSelection);
     let mut need_refresh_tui = false;
 
-    exit_on_error!(terminal.draw(|f| draw(f, &mut state)), terminal);
+    exit_on_error!(terminal.draw(|f| draw(f, &mut state)), terminal, state);
+
+    // Keys with the time they were read, which is what the debounce compares:
+    // keys buffered during a refresh are all handled at once afterwards.
+    let mut pending: Vec<(KeyEvent, Instant)> = vec![];
 
     loop {
         let timeout = refresh_data
             .checked_sub(last_tick.elapsed())
             .unwrap_or_else(|| Duration::from_secs(0));
+        let timeout = state
+            .status_expires_in()
+            .map_or(timeout, |left| timeout.min(left));
 
-        if crossterm::event::poll(timeout)? {
-            if let Event::Key(key) = event::read()? {
-                if key.kind == crossterm::event::KeyEventKind::Release {
-                    continue;
-                }
+        if pending.is_empty() && crossterm::event::poll(timeout)? {
+            match event::read()? {
+                Event::Key(key) => pending.push((key, Instant::now())),
+                Event::Resize(_, _) => need_refresh_tui = true,
+                _ => {}
+            }
+        }
 
-                match key.code {
-                    KeyCode::Char('q') => exit::<String>(&mut terminal, None)?,
-                    _ => {
-                        (need_refresh_data, need_refresh_tui) = state.key_pressed(key.code);
-                    }
-                }
+        if state.expire_status() {
+            need_refresh_tui = true;
+        }
+
+        for (key, read_at) in std::mem::take(&mut pending) {
+            if key.kind == crossterm::event::KeyEventKind::Release {
+                continue;
             }
+
+            if is_navigation(key.code)
+                && !state.is_typing()
+                && nav_debounce.is_repeat(key.code, read_at)
+            {
+                continue;
+            }
+
+            if is_quit(&key, state.is_typing()) {
+                exit::<String>(&mut terminal, &state, None)?
+            }
+            let (data, tui) = state.key_pressed(key.code);
+            need_refresh_data |= data;
+            need_refresh_tui |= tui;
         }
 
         if need_refresh_data || last_tick.elapsed() >= refresh_data {
-            exit_on_error!(state.refresh_data().await, terminal);
+            // Periodic refreshes keep the current data on screen, explicitly
+            // requested ones (new step, manual refresh) show that data is coming.
+            if need_refresh_data {
+                state.loading = true;
+                exit_on_error!(terminal.draw(|f| draw(f, &mut state)), terminal, state);
+            }
 
-            last_tick = Instant::now();
+            let started = Instant::now();
+            let typing = state.is_typing();
+            let refreshed = tokio::select! {
+                res = state.refresh_data() => Some(res),
+                quit = wait_for_quit(&mut pending, typing) => {
+                    quit?;
+                    None
+                }
+            };
+            match refreshed {
+                // Network errors are transient: keep the last data on screen and
+                // retry on the next tick instead of quitting.
+                Some(res) => {
+                    match &res {
+                        Ok(()) => failures = 0,
+                        Err(err) => {
+                            failures += 1;
+                            log::error!("refresh failed ({failures} in a row): {err}");
+                        }
+                    }
+                    state.refresh_error = res.err().map(|err| err.to_string())
+                }
+                None => exit::<String>(&mut terminal, &state, None)?,
+            }
+
+            // The interval is measured start-to-start, but a refresh that took
+            // longer than the interval pushes the next one a full interval away
+            // so that the UI still gets some idle time.
+            state.refresh_duration = Some(started.elapsed());
+            state.refresh_overrun = started.elapsed() >= refresh_data;
+            last_tick = if state.refresh_overrun {
+                Instant::now()
+            } else {
+                started
+            };
+            state.loading = false;
+            if state.take_bell() {
+                print!("\x07");
+                std::io::Write::flush(&mut std::io::stdout())?;
+            }
             need_refresh_data = false;
             need_refresh_tui = true;
         }
 
         if need_refresh_tui {
-            exit_on_error!(terminal.draw(|f| draw(f, &mut state)), terminal);
+            exit_on_error!(terminal.draw(|f| draw(f, &mut state)), terminal, state);
+            need_refresh_tui = false;
         }
     }
 }
 
+/// Keeps the app responsive to `q` while a refresh is in flight. Every other
+/// key is buffered into `pending` and handled once the refresh is done.
+async fn wait_for_quit(pending: &mut Vec<(KeyEvent, Instant)>, typing: bool) -> Result<()> {
+    loop {
+        while event::poll(Duration::ZERO)? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind != crossterm::event::KeyEventKind::Release && is_quit(&key, typing) {
+                    return Ok(());
+                }
+                pending.push((key, Instant::now()));
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+/// `q`, unless typed into a text field, and Ctrl+C, which raw mode delivers
+/// as a key instead of a signal.
+fn is_quit(key: &KeyEvent, typing: bool) -> bool {
+    match key.code {
+        KeyCode::Char('q') => !typing,
+        KeyCode::Char('c') => key.modifiers.contains(KeyModifiers::CONTROL),
+        _ => false,
+    }
+}
+
+fn is_navigation(key_code: KeyCode) -> bool {
+    matches!(
+        key_code,
+        KeyCode::Up | KeyCode::Down | KeyCode::Char('j') | KeyCode::Char('k')
+    )
+}
+
+/// Drops a navigation key repeating the previous one within the window,
+/// going by the time the keys were read rather than handled.
+struct NavDebounce {
+    window: Duration,
+    last: Option<(KeyCode, Instant)>,
+}
+
+impl NavDebounce {
+    fn new(window_ms: u64) -> NavDebounce {
+        NavDebounce {
+            window: Duration::from_millis(window_ms),
+            last: None,
+        }
+    }
+
+    fn is_repeat(&mut self, key_code: KeyCode, read_at: Instant) -> bool {
+        let repeat = self.last.is_some_and(|(code, at)| {
+            code == key_code && read_at.saturating_duration_since(at) < self.window
+        });
+        if !repeat {
+            self.last = Some((key_code, read_at));
+        }
+        repeat
+    }
+}
+
 fn exit<E: Display>(
     terminal: &mut Terminal<CrosstermBackend<std::io::Stdout>>,
+    state: &State,
     err: Option<E>,
 ) -> Result<()> {
+    let saved = persist::save(persist::SESSION_FILE, &state.session())
+        .and_then(|()| persist::save(persist::FILTERS_FILE, &state.filters()));
+
     disable_raw_mode()?;
     execute!(
         terminal.backend_mut(),
@@ -92,136 +281,870 @@ fn exit<E: Display>(
         DisableMouseCapture
     )?;
     terminal.show_cursor()?;
+    if let Err(err) = saved {
+        eprintln!("could not save the session: {err}");
+    }
+    // Only a clean quit reports success to the shell.
     if let Some(err) = err {
         eprintln!("{err}");
+        process::exit(1);
     }
     process::exit(0)
 }
 
 pub fn draw<B: Backend>(f: &mut Frame<B>, state: &mut State) {
+    let mut area = f.size();
+
+    let (min_width, min_height) = state.config.min_size;
+    if area.width < min_width || area.height < min_height {
+        f.render_widget(
+            Paragraph::new(format!(
+                "Terminal too small — resize to at least {min_width}x{min_height}"
+            ))
+            .wrap(Wrap { trim: true }),
+            area,
+        );
+        return;
+    }
+
+    if let Some(banner) = state.banner() {
+        let chunks = Layout::default()
+            .constraints([Constraint::Length(1), Constraint::Min(0)])
+            .split(area);
+        f.render_widget(Paragraph::new(banner).style(state.theme.banner), chunks[0]);
+        area = chunks[1];
+    }
+
+    let chunks = Layout::default()
+        .constraints([Constraint::Min(0), Constraint::Length(1)])
+        .split(area);
+    area = chunks[0];
+    f.render_widget(
+        Paragraph::new(match state.status.as_ref() {
+            _ if state.loading => String::from(" Loading…"),
+            Some(status) => format!(" {status}"),
+            None => String::from(" Press ? for help"),
+        })
+        .style(state.theme.footer),
+        chunks[1],
+    );
+    if let Some(style) = health_style(state) {
+        let footer = chunks[1];
+        f.render_widget(
+            Paragraph::new(" ●").style(style),
+            Rect::new(
+                footer.right().saturating_sub(2),
+                footer.y,
+                2.min(footer.width),
+                1,
+            ),
+        );
+    }
+
+    state.page_size = area.height.saturating_sub(2).max(1) as usize;
+
+    match state.step {
+        state::Step::ServerSelection => draw_server_selection(f, state, area),
+        state::Step::StationSelection if state.show_overview => draw_overview(f, state, area),
+        state::Step::StationSelection => draw_station_selection(f, state, area),
+        state::Step::EDR => {
+            if state.show_detail {
+                draw_train_detail(f, state, area)
+            } else {
+                draw_edr(f, state, area)
+            }
+        }
+    }
+
+    if state.show_help {
+        draw_help(f, state, area);
+    }
+}
+
+/// Key bindings available in the current step, as (keys, action) pairs.
+fn key_bindings(state: &State) -> Vec<(&'static str, &'static str)> {
+    let mut bindings = vec![
+        ("↑/k ↓/j", "Move the selection"),
+        ("PgUp/PgDn", "Move by a page"),
+        ("Home/End", "Jump to the first/last entry"),
+        ("Enter", "Select"),
+    ];
     match state.step {
-        state::Step::ServerSelection => draw_server_selection(f, state),
-        state::Step::StationSelection => draw_station_selection(f, state),
-        state::Step::EDR => draw_edr(f, state),
+        state::Step::ServerSelection => {
+            bindings.push(("a", "Hide/show the inactive servers"));
+            bindings.push(("g", "Group the servers by region"));
+        }
+        state::Step::StationSelection => {
+            bindings.push(("/", "Search stations"));
+            bindings.push(("A-Z 0-9", "Jump to a station code"));
+            bindings.push(("Space", "Mark the station for a combined board"));
+            bindings.push(("d", "List dispatched stations first"));
+            bindings.push(("f", "Add/remove the station from the favorites"));
+            bindings.push(("*", "Show only the favorites"));
+            bindings.push(("o", "Show/hide the staffed stations overview"));
+            bindings.push(("Esc", "Back to server selection"));
+        }
+        state::Step::EDR => {
+            bindings[3].1 = "Show/hide train details";
+            bindings.push(("Tab/S-Tab 1-9", "Switch board tab"));
+            bindings.push(("/", "Filter trains by name or number"));
+            bindings.push(("b", "Show/hide bot trains"));
+            bindings.push(("s", "Show/hide trains passing without stopping"));
+            bindings.push(("v", "Switch between all, arrivals and departures"));
+            bindings.push(("o", "Sort by time, delay or name"));
+            bindings.push(("c", "Cycle through the train categories shown"));
+            bindings.push(("w", "Limit to a time window around now"));
+            bindings.push(("t", "Show the actual time instead of the delay"));
+            bindings.push(("f", "Follow the next upcoming train"));
+            bindings.push(("g", "Group the board by line"));
+            bindings.push(("y", "Copy the train number"));
+            bindings.push(("p", "Add/remove the train from the watchlist"));
+            bindings.push(("e", "Export the board to CSV"));
+            bindings.push(("J", "Export the board to JSON"));
+            bindings.push(("Esc", "Back to station selection"));
+            bindings.push(("S", "Back to server selection"));
+        }
     }
+    bindings.push(("r", "Refresh now"));
+    bindings.push(("?", "Show this help"));
+    bindings.push(("q", "Quit"));
+    bindings
+}
+
+/// Green when the last refresh went fine, yellow when it was slow and red
+/// when it failed.
+fn health_style(state: &State) -> Option<Style> {
+    let duration = state.refresh_duration?;
+    Some(if state.refresh_error.is_some() {
+        state.theme.health_error
+    } else if duration > SLOW_REFRESH {
+        state.theme.health_slow
+    } else {
+        state.theme.health_ok
+    })
 }
 
-fn draw_edr<B: Backend>(f: &mut Frame<B>, state: &mut State) {
-    state.events.sort();
+fn draw_help<B: Backend>(f: &mut Frame<B>, state: &State, area: Rect) {
+    let mut bindings = key_bindings(state);
+    if matches!(state.step, state::Step::EDR) {
+        // Legend of the Cat column.
+        bindings.push(("", ""));
+        bindings.extend(
+            state::TrainCategory::ALL
+                .iter()
+                .map(|category| (category.tag(), category.description())),
+        );
+    }
+    let width = 50.min(area.width);
+    let height = (bindings.len() as u16 + 2).min(area.height);
+    let popup = Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    );
+
+    f.render_widget(Clear, popup);
     f.render_widget(
-        Table::new(state.events.iter().enumerate().map(|(i, e)| {
-            Row::new(vec![
-                if e.player { '*' } else { ' ' }.to_string(),
-                e.name.clone(),
-                match e.ty {
-                    state::EventType::Passing => "",
-                    state::EventType::Entering => "IN",
-                    state::EventType::Departing => "OUT",
+        Paragraph::new(
+            bindings
+                .into_iter()
+                .map(|(keys, action)| {
+                    Spans::from(vec![
+                        Span::styled(format!("{keys:>14} "), state.theme.emphasis),
+                        Span::raw(action),
+                    ])
+                })
+                .collect::<Vec<_>>(),
+        )
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(" Help — press any key "),
+        ),
+        popup,
+    );
+}
+
+fn draw_edr<B: Backend>(f: &mut Frame<B>, state: &mut State, mut area: Rect) {
+    if state.tabs.len() > 1 {
+        let chunks = Layout::default()
+            .constraints([Constraint::Length(1), Constraint::Min(0)])
+            .split(area);
+        f.render_widget(
+            Tabs::new(
+                (0..state.tabs.len())
+                    .map(|i| Spans::from(format!("{} {}", i + 1, state.board_name(i))))
+                    .collect(),
+            )
+            .select(state.tab_index)
+            .highlight_style(state.theme.selected),
+            chunks[0],
+        );
+        area = chunks[1];
+    }
+
+    // Borders and header row.
+    state.page_size = area.height.saturating_sub(3).max(1) as usize;
+
+    state.compact_board = f.size().width < state.config.compact_width;
+    let layout = state.board_layout();
+
+    // The actual time takes the place of the delay when the times are split,
+    // the time is otherwise followed by the delay, e.g. `14:32 (+3)`.
+    let (time_width, delay_width) = if state.split_times {
+        (state.config.time_width(), state.config.time_width())
+    } else {
+        (state.config.time_width() + 7, 5)
+    };
+
+    let name_width = if layout.compact {
+        // Whatever the borders, the other columns and the spacing between
+        // them leave.
+        let station_width = if layout.combined {
+            STATION_WIDTH + 1
+        } else {
+            0
+        };
+        let others = 2 + 2 + time_width + delay_width + 3 + station_width;
+        area.width.saturating_sub(others) as usize
+    } else {
+        area.width.saturating_sub(2) as usize * 25 / 100
+    }
+    .min(state.config.train_name_max);
+
+    let event_row = |e: &state::Event| {
+        let [marker, name, category, ty, station, time, delay, eta, platform, route, prev, next] =
+            state.board_columns(e);
+        let alert_style = state.theme.late;
+        let late = state.is_late(e);
+        let delay_style = match e.delay_minutes() {
+            _ if late => alert_style,
+            Some(delay) if delay > 0 => state.theme.delayed,
+            _ => Style::default(),
+        };
+        let cells = [
+            Cell::from(marker),
+            Cell::from(truncate(&name, name_width)),
+            Cell::from(category),
+            Cell::from(ty),
+            Cell::from(station),
+            if state.split_times {
+                Cell::from(time)
+            } else {
+                Cell::from(time).style(if late { alert_style } else { Style::default() })
+            },
+            if state.split_times {
+                Cell::from(delay).style(delay_style)
+            } else {
+                Cell::from(format!("{delay:>5}")).style(delay_style)
+            },
+            Cell::from(eta),
+            match &e.platform_change {
+                Some(previous) => {
+                    Cell::from(format!("{previous}→{platform}")).style(state.theme.platform_change)
                 }
-                .to_string(),
-                e.get_time(),
-                e.prev.clone(),
-                e.next.clone(),
+                None => Cell::from(platform),
+            },
+            Cell::from(route),
+            Cell::from(prev),
+            Cell::from(next),
+        ];
+        let style = event_style(&state.theme, e);
+        Row::new(layout.shown(cells)).style(if state.is_watched(e) {
+            style.patch(state.theme.watched)
+        } else {
+            style
+        })
+    };
+
+    // When grouped, each line opens with a header row that shifts the
+    // selected row down.
+    let mut rows = vec![];
+    let mut selected = None;
+    let mut line = None;
+    for (i, e) in state.visible_events().into_iter().enumerate() {
+        if state.group_by_line && line != Some(e.line) {
+            line = Some(e.line);
+            rows.push(
+                Row::new(vec![Cell::from(""), Cell::from(format!("Line {}", e.line))])
+                    .style(state.theme.emphasis),
+            );
+        }
+        if i == state.edr_index {
+            selected = Some(rows.len());
+        }
+        rows.push(event_row(e));
+    }
+    // Same empty state as the station list.
+    if rows.is_empty() {
+        let message = if state.train_query.is_empty() {
+            String::from("No trains")
+        } else {
+            format!("No trains match '{}'", state.train_query)
+        };
+        rows.push(Row::new(vec![Cell::from(""), Cell::from(message)]).style(state.theme.muted));
+    }
+
+    let mut _state = TableState::default();
+    _state.select(selected);
+
+    f.render_stateful_widget(
+        Table::new(rows)
+            .header(Row::new(layout.shown(state.board_header().map(
+                |title| match title.as_str() {
+                    "Delay" => format!("{title:>5}"),
+                    _ => title,
+                },
+            ))))
+            .widths(&layout.shown([
+                Constraint::Length(2),
+                if layout.compact {
+                    Constraint::Length(name_width as u16)
+                } else {
+                    Constraint::Percentage(25)
+                },
+                Constraint::Length(3),
+                Constraint::Length(4),
+                if layout.compact {
+                    Constraint::Length(STATION_WIDTH)
+                } else {
+                    Constraint::Percentage(15)
+                },
+                Constraint::Length(time_width),
+                Constraint::Length(delay_width),
+                Constraint::Length(state.config.time_width()),
+                Constraint::Length(9),
+                Constraint::Percentage(15),
+                Constraint::Percentage(20),
+                Constraint::Percentage(20),
+            ]))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(edr_title(state)),
+            )
+            .highlight_style(state.theme.selected),
+        area,
+        &mut _state,
+    )
+}
+
+fn event_style(theme: &Theme, event: &state::Event) -> Style {
+    let style = theme.event.patch(match event.ty {
+        state::EventType::Passing => theme.passing,
+        state::EventType::Entering => theme.entering,
+        state::EventType::Departing => theme.departing,
+    });
+    if event.player {
+        style.patch(theme.player)
+    } else {
+        style
+    }
+}
+
+/// Width of the station column of compact combined boards.
+const STATION_WIDTH: u16 = 16;
+
+fn edr_title(state: &State) -> String {
+    let mut title = format!(
+        " {}/{} ",
+        state.selected_server,
+        state.board_name(state.tab_index)
+    );
+    match state.view {
+        state::BoardView::All => {}
+        state::BoardView::Arrivals => title.push_str("Arrivals "),
+        state::BoardView::Departures => title.push_str("Departures "),
+    }
+    let events = state.visible_events();
+    title.push_str(&format!(
+        "— {} events ({} players) ",
+        events.len(),
+        events.iter().filter(|e| e.player).count()
+    ));
+    if let Some(update) = state.last_update {
+        title.push_str(&format!(
+            "— updated {} ",
+            state.config.format_time_secs(&update)
+        ));
+    }
+    if !state.show_bots {
+        title.push_str("[players only] ");
+    }
+    if state.stopping_only {
+        title.push_str("[stopping only] ");
+    }
+    if let Some((before, after)) = state.time_window {
+        title.push_str(&format!("[-{before}/+{after} min] "));
+    }
+    match state.sort {
+        state::SortMode::Time => {}
+        state::SortMode::Delay => title.push_str("[by delay] "),
+        state::SortMode::Name => title.push_str("[by name] "),
+    }
+    if !state.watched().is_empty() {
+        title.push_str(&format!("[watchlist ({})] ", state.watched().len()));
+    }
+    if let Some(category) = state.category_filter {
+        title.push_str(&format!("[{} only] ", category.tag()));
+    }
+    if state.follow {
+        title.push_str("[follow] ");
+    }
+    if state.group_by_line {
+        title.push_str("[by line] ");
+    }
+    if state.searching || !state.train_query.is_empty() {
+        title.push_str(&format!(
+            "/{}{} ",
+            state.train_query,
+            if state.searching { "_" } else { "" }
+        ));
+    }
+    title
+}
+
+fn format_distance(meters: f32) -> String {
+    if meters < 1000.0 {
+        format!("{meters:.0} m")
+    } else {
+        format!("{:.1} km", meters / 1000.0)
+    }
+}
+
+fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        text.to_string()
+    } else {
+        let mut text = text.chars().take(max.saturating_sub(1)).collect::<String>();
+        text.push('…');
+        text
+    }
+}
+
+fn draw_train_detail<B: Backend>(f: &mut Frame<B>, state: &State, area: Rect) {
+    let Some(event) = state.selected_event() else {
+        return;
+    };
+
+    let mut lines = vec![Spans::from(Span::styled(
+        event.name.clone(),
+        state.theme.emphasis,
+    ))];
+
+    if let Some(train) = state.selected_train() {
+        let data = &train.train_data;
+        let controller = match data.controlled_by_steam_id.as_ref() {
+            Some(steam_id) => state
+                .get_player_name(Some(steam_id))
+                .unwrap_or(steam_id)
+                .clone(),
+            None => String::from("bot"),
+        };
+
+        lines.extend([
+            Spans::from(format!("Type:       {}", train.t)),
+            Spans::from(format!("Controller: {controller}")),
+            Spans::from(format!("Route:      {} -> {}", train.start, train.end)),
+            Spans::from(format!("Consist:    {}", train.vehicles.join(", "))),
+            Spans::from(format!(
+                "Position:   {:.5}, {:.5}{}",
+                data.latitude,
+                data.longitude,
+                train
+                    .loc
+                    .as_ref()
+                    .map(|loc| format!(" (near {loc})"))
+                    .unwrap_or_default()
+            )),
+        ]);
+
+        // Bots report no meaningful live data, and a stopped train has no
+        // approach to anticipate.
+        if data.controlled_by_steam_id.is_some() {
+            if data.velocity > 0.0 {
+                lines.push(Spans::from(format!(
+                    "Speed:      {:.0} km/h",
+                    data.velocity
+                )));
+                if let Some(signal) = data.signal_in_front.as_ref() {
+                    lines.push(Spans::from(format!(
+                        "Signal:     {} in {}",
+                        signal,
+                        format_distance(data.distance_to_signal_in_front)
+                    )));
+                }
+            } else {
+                lines.push(Spans::from("Speed:      stopped"));
+            }
+        }
+
+        if let Some(station) = state.event_station(event) {
+            lines.push(Spans::from(format!(
+                "Distance:   {:.1} km to {}",
+                train.dist_from(station),
+                station.name
+            )));
+            if let Some(eta) = train.eta_to(station) {
+                let arrival = Utc::now() + eta;
+                lines.push(Spans::from(format!(
+                    "ETA:        {}:{:02} (around {})",
+                    eta.num_minutes(),
+                    eta.num_seconds() % 60,
+                    state.config.format_time(&arrival)
+                )));
+            }
+        }
+    }
+
+    let chunks = Layout::default()
+        .constraints([
+            Constraint::Length(lines.len() as u16 + 2),
+            Constraint::Min(0),
+        ])
+        .split(area);
+
+    f.render_widget(
+        Paragraph::new(lines).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!(" {}/{} ", state.selected_server, event.name)),
+        ),
+        chunks[0],
+    );
+
+    let timetable = state.selected_timetable();
+    let station_pos = timetable
+        .iter()
+        .position(|s| s.name_of_point == event.station);
+    let actual = |time: Option<DateTime<Utc>>| {
+        time.map(|time| state.config.format_time(&time))
+            .unwrap_or_default()
+    };
+
+    let mut table_state = TableState::default();
+    table_state.select(Some(state.detail_index));
+
+    f.render_stateful_widget(
+        Table::new(timetable.iter().enumerate().map(|(i, stop)| {
+            Row::new(vec![
+                stop.name_of_point.clone(),
+                format!("L.{}", stop.line),
+                state.config.format_time(&stop.scheduled_arrival_object),
+                actual(stop.actual_arrival()),
+                state.config.format_time(&stop.scheduled_departure_object),
+                actual(stop.actual_departure()),
+                stop.platform
+                    .as_ref()
+                    .zip(stop.track)
+                    .map(|(platform, track)| format!("{platform}/{track}"))
+                    .unwrap_or_default(),
             ])
-            .style(Style::default().add_modifier(Modifier::UNDERLINED))
+            .style(if Some(i) == station_pos {
+                state.theme.emphasis
+            } else {
+                Style::default()
+            })
         }))
-        .header(Row::new(vec!["", "Train", "", "Time", "From", "To"]))
+        .header(Row::new(vec![
+            "Stop", "Line", "Arr", "Act", "Dep", "Act", "Platform",
+        ]))
         .widths(&[
-            Constraint::Length(2),
-            Constraint::Percentage(30),
-            Constraint::Length(4),
-            Constraint::Length(6),
-            Constraint::Percentage(30),
-            Constraint::Percentage(30),
+            Constraint::Percentage(40),
+            Constraint::Length(7),
+            Constraint::Length(state.config.time_width() + 1),
+            Constraint::Length(state.config.time_width() + 1),
+            Constraint::Length(state.config.time_width() + 1),
+            Constraint::Length(state.config.time_width() + 1),
+            Constraint::Length(9),
         ])
-        .block(Block::default().borders(Borders::ALL).title(
-            format!(" {}/{} ",state.selected_server,state
-                    .selected_station
-                    .as_ref()
-                    .expect("selected station is none")
-                    .name
-                    .clone(),),
+        .highlight_style(state.theme.selected)
+        .block(Block::default().borders(Borders::ALL).title(" Timetable ")),
+        chunks[1],
+        &mut table_state,
+    );
+}
+
+/// Dispatcher count and names of a station, only the first few names are
+/// listed to keep the line short.
+fn dispatchers(state: &State, station: &data::Station) -> String {
+    const SHOWN: usize = 2;
+
+    let count = station.dispatched_by.len();
+    if count == 0 {
+        return String::new();
+    }
+    // Players whose name is not resolved yet are shown by their id, as in the
+    // overview.
+    let names: Vec<&str> = station
+        .dispatched_by
+        .iter()
+        .take(SHOWN)
+        .map(|p| {
+            state
+                .get_player_name(Some(&p.steam_id))
+                .unwrap_or(&p.steam_id)
+                .as_str()
+        })
+        .collect();
+    let mut text = format!(" - [{count}] {}", names.join("/"));
+    if count > SHOWN {
+        text.push_str(&format!(" (+{})", count - SHOWN));
+    }
+    text
+}
+
+/// Which stations of the server are staffed, and by whom.
+fn draw_overview<B: Backend>(f: &mut Frame<B>, state: &State, area: Rect) {
+    let staffed: Vec<_> = state
+        .stations
+        .iter()
+        .filter(|s| !s.dispatched_by.is_empty())
+        .collect();
+
+    let mut lines = vec![
+        Spans::from(format!(
+            " {} stations, {} dispatched, {} free",
+            state.stations.len(),
+            staffed.len(),
+            state.stations.len() - staffed.len()
         )),
-        f.size(),
-    )
+        Spans::default(),
+    ];
+    lines.extend(staffed.into_iter().map(|s| {
+        let style = state.theme.color(
+            state
+                .config
+                .dispatcher_color(s.dispatched_by.iter().map(|p| &p.steam_id)),
+        );
+        Spans::from(Span::styled(
+            format!(
+                " {} \t {} - {}",
+                s.prefix,
+                s.name,
+                s.dispatched_by
+                    .iter()
+                    .map(|p| state
+                        .get_player_name(Some(&p.steam_id))
+                        .unwrap_or(&p.steam_id)
+                        .clone())
+                    .collect::<Vec<_>>()
+                    .join("/")
+            ),
+            style,
+        ))
+    }));
+
+    f.render_widget(
+        Paragraph::new(lines).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!(" {}/Overview ", state.selected_server)),
+        ),
+        area,
+    );
 }
 
-fn draw_station_selection<B: Backend>(f: &mut Frame<B>, state: &State) {
+fn draw_station_selection<B: Backend>(f: &mut Frame<B>, state: &State, area: Rect) {
+    let matches = state.visible_stations().len();
     let mut _state = ListState::default();
-    _state.select(Some(state.station_index));
+    _state.select((matches > 0).then_some(state.station_index));
 
     f.render_stateful_widget(
-        List::new(
+        List::new(placeholder_if_empty(
             state
-                .stations
-                .iter()
+                .visible_stations()
+                .into_iter()
                 .map(|s| {
                     ListItem::new(format!(
-                        "{} \t {}{}{}",
-                        s.prefix,
-                        s.name,
-                        if s.dispatched_by.is_empty() {
-                            ""
+                        "{}{}{} \t {}{}",
+                        if state.marked_stations.contains(&s.name) {
+                            "+"
                         } else {
-                            " - "
+                            " "
                         },
-                        s.dispatched_by
-                            .iter()
-                            .flat_map(|s| state.get_player_name(Some(&s.steam_id)))
-                            .cloned()
-                            .collect::<Vec<_>>()
-                            .join("/")
+                        if state.is_favorite(s) { "★ " } else { "  " },
+                        s.prefix,
+                        s.name,
+                        dispatchers(state, s)
                     ))
-                    .style(Style::default().add_modifier(
-                        if s.dispatched_by.is_empty() {
-                            Modifier::empty()
+                    .style({
+                        let style = if s.dispatched_by.is_empty() {
+                            Style::default()
                         } else {
-                            Modifier::BOLD
-                        },
-                    ))
+                            state.theme.emphasis
+                        };
+                        style.patch(
+                            state.theme.color(
+                                state
+                                    .config
+                                    .dispatcher_color(s.dispatched_by.iter().map(|p| &p.steam_id)),
+                            ),
+                        )
+                    })
                 })
-                .collect::<Vec<_>>(),
-        )
-        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .title(format!(" {}/Station Selection ", state.selected_server)),
-        ),
-        f.size(),
+                .collect(),
+            if state.station_query.is_empty() {
+                String::from("No stations")
+            } else {
+                format!("No stations match '{}'", state.station_query)
+            },
+            state.theme.muted,
+        ))
+        .highlight_style(state.theme.selected)
+        .block(Block::default().borders(Borders::ALL).title(format!(
+            " {}/Station Selection {}{}",
+            state.selected_server,
+            match (state.favorites_only, state.dispatched_first) {
+                (true, true) => "[favorites, dispatched first] ",
+                (true, false) => "[favorites] ",
+                (false, true) => "[dispatched first] ",
+                (false, false) => "",
+            },
+            if state.searching || !state.station_query.is_empty() {
+                format!(
+                    "/{}{} ({matches}) ",
+                    state.station_query,
+                    if state.searching { "_" } else { "" }
+                )
+            } else {
+                String::new()
+            }
+        ))),
+        area,
         &mut _state,
     );
 }
 
-fn draw_server_selection<B: Backend>(f: &mut Frame<B>, state: &State) {
+/// Lists without entries show a dimmed message instead of a blank frame.
+fn placeholder_if_empty<'a>(
+    items: Vec<ListItem<'a>>,
+    message: impl Into<Text<'a>>,
+    style: Style,
+) -> Vec<ListItem<'a>> {
+    if items.is_empty() {
+        vec![ListItem::new(message).style(style)]
+    } else {
+        items
+    }
+}
+
+fn draw_server_selection<B: Backend>(f: &mut Frame<B>, state: &State, area: Rect) {
+    // When grouped, each region opens with a header item that shifts the
+    // selected item down.
+    let mut items = vec![];
+    let mut selected = None;
+    let mut region = None;
+    for (i, s) in state.visible_servers().into_iter().enumerate() {
+        if state.group_servers_by_region && region.as_ref() != Some(&s.region()) {
+            region = Some(s.region());
+            items.push(
+                ListItem::new(s.region().unwrap_or_else(|| String::from("Other")))
+                    .style(state.theme.emphasis),
+            );
+        }
+        if i == state.server_index {
+            selected = Some(items.len());
+        }
+        items.push(
+            ListItem::new(format!("{} {}", s.server_code, s.server_name)).style(if s.is_active {
+                Style::default()
+            } else {
+                state.theme.inactive_server
+            }),
+        );
+    }
+
     let mut _state = ListState::default();
-    _state.select(Some(state.server_index));
+    _state.select(selected);
+
+    let mut title = String::from(" Server Selection ");
+    if state.active_servers_only {
+        title.push_str("[active only] ");
+    }
+    if state.group_servers_by_region {
+        title.push_str("[by region] ");
+    }
 
     f.render_stateful_widget(
-        List::new(
-            state
-                .servers
-                .iter()
-                .map(|s| {
-                    ListItem::new(format!("{} {}", s.server_code, s.server_name)).style(
-                        Style::default().add_modifier({
-                            let mut modifier = Modifier::empty();
-                            if !s.is_active {
-                                modifier.toggle(Modifier::CROSSED_OUT)
-                            }
-                            modifier
-                        }),
-                    )
-                })
-                .collect::<Vec<_>>(),
-        )
-        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .title("Server Selection"),
-        ),
-        f.size(),
+        List::new(placeholder_if_empty(
+            items,
+            if state.active_servers_only {
+                "No active servers available"
+            } else {
+                "No servers available"
+            },
+            state.theme.muted,
+        ))
+        .highlight_style(state.theme.selected)
+        .block(Block::default().borders(Borders::ALL).title(title)),
+        area,
         &mut _state,
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use wiremock::matchers::path;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+
+    #[test]
+    fn buffered_keys_are_debounced_by_their_read_time() {
+        let mut debounce = NavDebounce::new(40);
+        let start = Instant::now();
+        // Three presses read during a refresh, handled together afterwards.
+        let presses = [0, 300, 600].map(|ms| start + Duration::from_millis(ms));
+        for read_at in presses {
+            assert!(!debounce.is_repeat(KeyCode::Down, read_at));
+        }
+        // A key repeating within the window is dropped, another key is not.
+        let bounce = presses[2] + Duration::from_millis(10);
+        assert!(debounce.is_repeat(KeyCode::Down, bounce));
+        assert!(!debounce.is_repeat(KeyCode::Up, bounce));
+    }
+
+    #[test]
+    fn a_zero_window_disables_the_debounce() {
+        let mut debounce = NavDebounce::new(0);
+        let now = Instant::now();
+        assert!(!debounce.is_repeat(KeyCode::Down, now));
+        assert!(!debounce.is_repeat(KeyCode::Down, now));
+    }
+
+    #[tokio::test]
+    async fn unresolved_dispatchers_are_listed_by_id() {
+        let server = MockServer::start().await;
+        Mock::given(path("/servers-open"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({ "result": true, "data": [] })),
+            )
+            .mount(&server)
+            .await;
+        let mut state = State::new(Config {
+            panel_url: Some(server.uri()),
+            ..Config::default()
+        })
+        .await
+        .unwrap();
+        state
+            .player_names
+            .insert(String::from("1"), String::from("Alice"));
+
+        let station = data::Station {
+            name: String::from("Katowice"),
+            prefix: String::from("KO"),
+            dispatched_by: ["1", "2", "3"]
+                .map(|id| data::Player {
+                    steam_id: id.to_string(),
+                })
+                .to_vec(),
+            latitude: 50.2587,
+            longitude: 19.0172,
+        };
+        assert_eq!(dispatchers(&state, &station), " - [3] Alice/2 (+1)");
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::state::{BoardView, SortMode, TrainCategory};
+
+pub const SESSION_FILE: &str = "session.ron";
+pub const FAVORITES_FILE: &str = "favorites.ron";
+pub const WATCHLIST_FILE: &str = "watchlist.ron";
+pub const FILTERS_FILE: &str = "filters.ron";
+pub const LOG_FILE: &str = "debug.log";
+
+/// Server code -> names of the stations bookmarked on that server.
+pub type Favorites = HashMap<String, Vec<String>>;
+
+/// Server code -> numbers of the trains watched on that server.
+pub type Watchlist = HashMap<String, Vec<String>>;
+
+/// What was selected when the app was last closed.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    pub server: Option<String>,
+    pub station: Option<String>,
+}
+
+/// Board filters, kept apart from the session so that either can be reset
+/// without the other.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Filters {
+    pub show_bots: bool,
+    pub stopping_only: bool,
+    pub time_window: Option<(i64, i64)>,
+    pub category: Option<TrainCategory>,
+    pub group_by_line: bool,
+    pub view: BoardView,
+    pub sort: SortMode,
+    pub active_servers_only: bool,
+    pub group_servers_by_region: bool,
+}
+
+impl Default for Filters {
+    fn default() -> Self {
+        Self {
+            show_bots: true,
+            stopping_only: false,
+            time_window: None,
+            category: None,
+            group_by_line: false,
+            view: BoardView::All,
+            sort: SortMode::Time,
+            active_servers_only: false,
+            group_servers_by_region: false,
+        }
+    }
+}
+
+/// Loads `<data dir>/edr/<name>`, falling back to the default value when the
+/// file is missing or malformed.
+pub fn load<T: DeserializeOwned + Default>(name: &str) -> T {
+    path(name)
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|content| ron::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+pub fn save<T: Serialize>(name: &str, value: &T) -> crate::Result<()> {
+    let path = path(name).ok_or("no data directory available")?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, ron::to_string(value)?)?;
+    Ok(())
+}
+
+pub fn path(name: &str) -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("edr").join(name))
+}
+
+/// Sends the log records of the app to `<data dir>/edr/debug.log`, the
+/// terminal belongs to the TUI.
+pub fn init_log() -> crate::Result<()> {
+    let path = path(LOG_FILE).ok_or("no data directory available")?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)?;
+    let config = simplelog::ConfigBuilder::new()
+        .add_filter_allow_str(env!("CARGO_CRATE_NAME"))
+        .set_time_format_rfc3339()
+        .build();
+    simplelog::WriteLogger::init(log::LevelFilter::Debug, config, file)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_round_trip() {
+        let filters = Filters {
+            show_bots: false,
+            stopping_only: true,
+            time_window: Some((15, 60)),
+            category: Some(TrainCategory::Freight),
+            group_by_line: true,
+            view: BoardView::Departures,
+            sort: SortMode::Delay,
+            active_servers_only: true,
+            group_servers_by_region: true,
+        };
+        let saved = ron::to_string(&filters).unwrap();
+        assert_eq!(ron::from_str::<Filters>(&saved).unwrap(), filters);
+    }
+
+    #[test]
+    fn missing_filters_keep_their_default() {
+        let filters: Filters = ron::from_str("(stopping_only: true)").unwrap();
+        assert_eq!(
+            filters,
+            Filters {
+                stopping_only: true,
+                ..Filters::default()
+            }
+        );
+    }
+}
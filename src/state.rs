@@ -1,24 +1,132 @@
-use chrono::{DateTime, Timelike, Utc};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
 use crossterm::event::KeyCode;
+use futures::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
 
+use crate::api::Api;
+use crate::config::{Column, Config};
 use crate::data::{
-    Server, ServerResponse, Station, StationResponse, SteamPlayer, SteamPlayers, StopDescription,
-    Train, TrainResponse,
+    distance_km, eta, Server, ServerResponse, Station, StationResponse, SteamPlayers,
+    StopDescription, Train, TrainResponse,
 };
+use crate::export;
+use crate::locator::StationLocator;
+use crate::persist::{self, Favorites, Filters, Session, Watchlist};
+use crate::theme::Theme;
+
+/// Pause after which the station prefix quick jump starts over.
+const TYPE_AHEAD_IDLE: Duration = Duration::from_secs(1);
+
+/// Number of timetables fetched at the same time during an EDR refresh.
+const TIMETABLE_FETCH_CONCURRENCY: usize = 16;
 
 pub struct State {
+    pub client: reqwest::Client,
+    pub api: Api,
+
     pub servers: Vec<Server>,
+    /// Index into `visible_servers`.
     pub server_index: usize,
+    /// Hides the inactive servers from the server selection.
+    pub active_servers_only: bool,
+    /// Lists the servers region by region, in the order the regions first appear.
+    pub group_servers_by_region: bool,
     pub selected_server: String,
+    pub server_offline: bool,
+    pub refresh_overrun: bool,
+    pub refresh_error: Option<String>,
+    /// How long the last refresh took, `None` before the first one.
+    pub refresh_duration: Option<Duration>,
+    pub last_update: Option<DateTime<Utc>>,
+    /// Set while a refresh the user is waiting for is running.
+    pub loading: bool,
+    /// Transient message shown in the footer, see `set_status`.
+    pub status: Option<String>,
+    status_since: Instant,
 
     pub stations: Vec<Station>,
+    locator: StationLocator,
     pub station_index: usize,
+    pub searching: bool,
+    pub station_query: String,
+    /// Narrows the board to the trains whose name or number contains it.
+    pub train_query: String,
+    /// Stations picked with Space to be shown on one combined board.
+    pub marked_stations: Vec<String>,
+    /// Lists the staffed stations before the others.
+    pub dispatched_first: bool,
+    pub favorites: Favorites,
+    /// Trains highlighted and pinned to the top of the boards.
+    pub watchlist: Watchlist,
+    /// Watched trains already announced as arriving, forgotten once they leave
+    /// so that the next arrival is announced again.
+    arrived: HashSet<String>,
+    /// Set when the terminal bell should ring, cleared by `take_bell`.
+    bell: bool,
+    /// Stations reported without coordinates, warned about when it changes.
+    unlocated_stations: usize,
+    /// Trains reported without coordinates, warned about when it changes.
+    unlocated_trains: usize,
+    pub favorites_only: bool,
+    /// Station prefix typed so far for the quick jump, see `type_ahead`.
+    prefix_typed: String,
+    prefix_typed_at: Instant,
 
     pub selected_station: Option<Station>,
-    pub players: Vec<SteamPlayer>,
+    /// Steam id -> persona name of every player looked up so far.
+    pub player_names: HashMap<String, String>,
 
     pub step: Step,
+    /// Rows visible in the current list, updated on every draw.
+    pub page_size: usize,
+    /// Whether the board was last drawn in its compact layout, updated on
+    /// every draw.
+    pub compact_board: bool,
+    pub tabs: Vec<String>,
+    /// Stations merged into the first tab, empty for a single station board.
+    pub combined: Vec<String>,
+    pub tab_index: usize,
+    pub boards: HashMap<String, Vec<Event>>,
     pub events: Vec<Event>,
+    pub edr_index: usize,
+    pub show_detail: bool,
+    /// Highlighted stop of the timetable shown in the detail view.
+    pub detail_index: usize,
+    pub show_help: bool,
+    /// Replaces the station list with a summary of the staffed stations.
+    pub show_overview: bool,
+    pub show_bots: bool,
+    /// Hides the trains passing through without stopping.
+    pub stopping_only: bool,
+    pub view: BoardView,
+    pub sort: SortMode,
+    /// Shows only the trains of this category.
+    pub category_filter: Option<TrainCategory>,
+    /// Minutes before and after now an event must fall in to be shown, the
+    /// whole timetable is shown when unset.
+    pub time_window: Option<(i64, i64)>,
+    /// Shows the scheduled and the actual time in two columns instead of the
+    /// time and the delay.
+    pub split_times: bool,
+    /// Keeps the next upcoming event selected across refreshes, until the
+    /// board is scrolled by hand.
+    pub follow: bool,
+    /// Groups the board by line, sorted by time within each line.
+    pub group_by_line: bool,
+
+    pub trains: Vec<Train>,
+    pub timetables: HashMap<String, Vec<StopDescription>>,
+    pub platform_tracker: PlatformTracker,
+    /// Opened on the first copy and kept, on X11 the copied text is gone once
+    /// the clipboard is dropped.
+    clipboard: Option<arboard::Clipboard>,
+
+    pub theme: Theme,
+    pub config: Config,
 }
 
 #[allow(clippy::upper_case_acronyms)]
@@ -28,332 +136,2637 @@ pub enum Step {
     EDR,
 }
 
-#[derive(Eq, PartialEq, Debug)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Event {
     pub name: String,
+    /// Station the event happens at, tells events apart on combined boards.
+    pub station: String,
     pub time: Option<DateTime<Utc>>,
     pub planned_time: DateTime<Utc>,
     pub ty: EventType,
 
     pub player: bool,
+    /// Steam id of the player driving the train.
+    pub driver: Option<String>,
+    pub train_no: String,
+    pub category: TrainCategory,
+    /// Origin and terminus of the train.
+    pub start: String,
+    pub end: String,
+    /// Which call at the station this is, only set for trains calling more than once.
+    pub visit: Option<usize>,
+    /// Platform and track the train stops at, `None` when it does not stop.
+    pub platform: Option<String>,
+    /// Platform the train was assigned before a recent reassignment.
+    pub platform_change: Option<String>,
+    /// Rail line the train reaches the station on.
+    pub line: i64,
+    /// Estimated arrival from the current speed, only for the arrival of
+    /// player trains still moving.
+    pub eta: Option<DateTime<Utc>>,
 
     pub prev: String,
     pub next: String,
 }
 
 impl Event {
-    pub fn get_time(&self) -> String {
-        if let Some(time) = self.time {
-            todo!()
-        } else {
-            format!(
-                "{:0>2}:{:0>2}",
-                self.planned_time.hour(),
-                self.planned_time.minute()
-            )
+    /// The actual time once the train reported one, the planned time otherwise.
+    pub fn effective_time(&self) -> DateTime<Utc> {
+        self.time.unwrap_or(self.planned_time)
+    }
+
+    /// The effective time, followed by the delay once the train reported an
+    /// actual time off the schedule, e.g. `14:32 (+3)`.
+    pub fn get_time(&self, config: &Config) -> String {
+        let time = config.format_time(&self.effective_time());
+        match self.delay_minutes() {
+            Some(delay) if delay != 0 => format!("{time} ({delay:+})"),
+            _ => time,
         }
     }
+
+    /// Minutes between the actual and the planned time, positive when late.
+    /// `None` until the train reported an actual time.
+    pub fn delay_minutes(&self) -> Option<i64> {
+        self.time
+            .map(|time| (time - self.planned_time).num_minutes())
+    }
+
+    pub fn get_delay(&self) -> String {
+        self.delay_minutes()
+            .map(|delay| format!("{delay:+}"))
+            .unwrap_or_default()
+    }
 }
 
 impl Ord for Event {
+    /// Events compare by their effective time: the actual time once the train
+    /// reported one, the planned time otherwise. An actual time can therefore
+    /// move a late train behind events that are still only planned. Events
+    /// sharing the same time are ordered by type, then by train name, station,
+    /// train number and visit, which tell every event of a board apart.
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        todo!()
+        self.effective_time()
+            .cmp(&other.effective_time())
+            .then_with(|| self.ty.cmp(&other.ty))
+            .then_with(|| self.name.cmp(&other.name))
+            .then_with(|| self.station.cmp(&other.station))
+            .then_with(|| self.train_no.cmp(&other.train_no))
+            .then_with(|| self.visit.cmp(&other.visit))
+    }
+}
+
+/// Equality follows the ordering, two snapshots of the same call are equal
+/// even when their estimated arrival or platform changed in between.
+impl PartialEq for Event {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
     }
 }
 
+impl Eq for Event {}
+
 impl PartialOrd for Event {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        let a_time = self.time.unwrap_or(self.planned_time);
-        let b_time = other.time.unwrap_or(other.planned_time);
+        Some(self.cmp(other))
+    }
+}
+
+/// Remembers the platform each train got at a station so that reassignments
+/// between two refreshes can be highlighted on the board.
+#[derive(Default)]
+pub struct PlatformTracker {
+    platforms: HashMap<(String, String), PlatformRecord>,
+}
+
+struct PlatformRecord {
+    platform: Option<String>,
+    previous: Option<String>,
+    changed_at: Option<Instant>,
+    /// Set by `update`, cleared by `prune`.
+    seen: bool,
+}
+
+impl PlatformTracker {
+    /// Records the current platform and returns the previous one if it was
+    /// reassigned less than `window` ago.
+    pub fn update(
+        &mut self,
+        train_no: &str,
+        station: &str,
+        platform: Option<&String>,
+        window: Duration,
+    ) -> Option<String> {
+        let record = self
+            .platforms
+            .entry((train_no.to_string(), station.to_string()))
+            .or_insert_with(|| PlatformRecord {
+                platform: platform.cloned(),
+                previous: None,
+                changed_at: None,
+                seen: false,
+            });
+        record.seen = true;
+
+        if record.platform.as_ref() != platform {
+            record.previous = std::mem::replace(&mut record.platform, platform.cloned());
+            record.changed_at = Some(Instant::now());
+        }
+
+        match record.changed_at {
+            Some(at) if at.elapsed() < window => {
+                Some(record.previous.clone().unwrap_or_else(|| String::from("-")))
+            }
+            _ => None,
+        }
+    }
 
-        a_time.partial_cmp(&b_time)
+    /// Forgets the trains and stations not updated since the last call, once
+    /// a refresh is through, so that the records do not pile up over a session.
+    pub fn prune(&mut self) {
+        self.platforms
+            .retain(|_, record| std::mem::take(&mut record.seen));
     }
 }
 
-#[derive(PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Serialize)]
 pub enum EventType {
     Passing,
     Entering,
     Departing,
 }
 
+impl EventType {
+    pub fn label(&self) -> &'static str {
+        match self {
+            EventType::Passing => "",
+            EventType::Entering => "IN",
+            EventType::Departing => "OUT",
+        }
+    }
+}
+
+/// Which events the board lists, like the separate arrivals and departures
+/// boards of a real station.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum BoardView {
+    #[default]
+    All,
+    Arrivals,
+    Departures,
+}
+
+impl BoardView {
+    fn next(self) -> BoardView {
+        match self {
+            BoardView::All => BoardView::Arrivals,
+            BoardView::Arrivals => BoardView::Departures,
+            BoardView::Departures => BoardView::All,
+        }
+    }
+
+    fn shows(self, ty: &EventType) -> bool {
+        match self {
+            BoardView::All => true,
+            BoardView::Arrivals => *ty == EventType::Entering,
+            BoardView::Departures => *ty == EventType::Departing,
+        }
+    }
+}
+
+/// Order of the board events.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum SortMode {
+    #[default]
+    Time,
+    /// Most delayed first, the trains without a delay yet come last.
+    Delay,
+    Name,
+}
+
+impl SortMode {
+    fn next(self) -> SortMode {
+        match self {
+            SortMode::Time => SortMode::Delay,
+            SortMode::Delay => SortMode::Name,
+            SortMode::Name => SortMode::Time,
+        }
+    }
+}
+
+/// Service category, read from the train name, e.g. `EIJ` or `ROJ`.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum TrainCategory {
+    /// EIP, EIC and EC services, names starting with `E`.
+    Express,
+    /// InterCity and TLK services, names starting with `M`.
+    InterCity,
+    /// Regional services, names starting with `R`.
+    Regional,
+    /// Freight trains, names starting with `T`.
+    Freight,
+    /// Light engines and service runs, names starting with `L` or `P`.
+    Service,
+    Other,
+}
+
+impl TrainCategory {
+    pub const ALL: [TrainCategory; 6] = [
+        TrainCategory::Express,
+        TrainCategory::InterCity,
+        TrainCategory::Regional,
+        TrainCategory::Freight,
+        TrainCategory::Service,
+        TrainCategory::Other,
+    ];
+
+    /// Unknown names are not an error, the category is only informative.
+    pub fn parse(train_name: &str) -> TrainCategory {
+        match train_name
+            .trim()
+            .chars()
+            .next()
+            .map(|c| c.to_ascii_uppercase())
+        {
+            Some('E') => TrainCategory::Express,
+            Some('M') => TrainCategory::InterCity,
+            Some('R') => TrainCategory::Regional,
+            Some('T') => TrainCategory::Freight,
+            Some('L' | 'P') => TrainCategory::Service,
+            _ => TrainCategory::Other,
+        }
+    }
+
+    pub fn tag(&self) -> &'static str {
+        match self {
+            TrainCategory::Express => "EXP",
+            TrainCategory::InterCity => "IC",
+            TrainCategory::Regional => "REG",
+            TrainCategory::Freight => "CGO",
+            TrainCategory::Service => "SRV",
+            TrainCategory::Other => "OTH",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            TrainCategory::Express => "Express (EIP, EIC, EC)",
+            TrainCategory::InterCity => "InterCity and TLK",
+            TrainCategory::Regional => "Regional",
+            TrainCategory::Freight => "Freight",
+            TrainCategory::Service => "Light engine or service run",
+            TrainCategory::Other => "Unknown category",
+        }
+    }
+}
+
+pub const BOARD_HEADER: [&str; 12] = [
+    "", "Train", "Cat", "", "Station", "Time", "Delay", "ETA", "Platform", "Route", "From", "To",
+];
+
+/// Which of the configured board columns are shown: the station only tells
+/// events apart on combined boards, and narrow terminals keep just the train,
+/// time and delay.
+pub struct BoardLayout<'a> {
+    pub columns: &'a [Column],
+    pub combined: bool,
+    pub compact: bool,
+}
+
+impl BoardLayout<'_> {
+    pub fn shown<T>(&self, columns: [T; BOARD_HEADER.len()]) -> Vec<T> {
+        let mut columns = columns.map(Some);
+        self.columns
+            .iter()
+            .filter(|column| match column {
+                Column::Station => self.combined,
+                Column::Marker | Column::Train | Column::Time | Column::Delay => true,
+                _ => !self.compact,
+            })
+            .filter_map(|&column| columns[column as usize].take())
+            .collect()
+    }
+}
+
 impl State {
-    pub async fn new() -> crate::Result<State> {
-        let servers = get_servers().await?;
+    pub async fn new(config: Config) -> crate::Result<State> {
+        if config.danger_accept_invalid_certs {
+            log::warn!("TLS certificate verification is disabled");
+        }
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.request_timeout_secs))
+            .danger_accept_invalid_certs(config.danger_accept_invalid_certs)
+            .build()?;
+        let api = Api::new(&config);
+        let servers = get_servers(&client, &api).await?;
+
+        let mut state = Self {
+            client,
+            api,
 
-        Ok(Self {
             servers,
             server_index: 0,
+            active_servers_only: false,
+            group_servers_by_region: false,
             selected_server: String::new(),
+            server_offline: false,
+            refresh_overrun: false,
+            refresh_error: None,
+            refresh_duration: None,
+            last_update: None,
+            loading: false,
+            status: None,
+            status_since: Instant::now(),
 
             stations: vec![],
+            locator: StationLocator::default(),
             station_index: 0,
+            searching: false,
+            station_query: String::new(),
+            train_query: String::new(),
+            marked_stations: vec![],
+            dispatched_first: false,
+            favorites: persist::load(persist::FAVORITES_FILE),
+            watchlist: persist::load(persist::WATCHLIST_FILE),
+            arrived: HashSet::new(),
+            bell: false,
+            unlocated_stations: 0,
+            unlocated_trains: 0,
+            favorites_only: false,
+            prefix_typed: String::new(),
+            prefix_typed_at: Instant::now(),
             selected_station: None,
 
-            players: vec![],
+            player_names: HashMap::new(),
 
             step: Step::ServerSelection,
+            page_size: 1,
+            compact_board: false,
+            tabs: vec![],
+            combined: vec![],
+            tab_index: 0,
+            boards: HashMap::new(),
             events: vec![],
-        })
+            edr_index: 0,
+            show_detail: false,
+            detail_index: 0,
+            show_help: false,
+            show_overview: false,
+            show_bots: true,
+            stopping_only: false,
+            view: BoardView::All,
+            sort: SortMode::Time,
+            category_filter: None,
+            follow: false,
+            group_by_line: false,
+            time_window: None,
+            split_times: config.split_times,
+
+            trains: vec![],
+            timetables: HashMap::new(),
+            platform_tracker: PlatformTracker::default(),
+            clipboard: None,
+            theme: Theme::new(config.theme, config.delay_alert_color),
+
+            config,
+        };
+        if state.config.danger_accept_invalid_certs {
+            state.set_status(String::from(
+                "Warning: TLS certificates are not verified (danger_accept_invalid_certs)",
+            ));
+        }
+        Ok(state)
+    }
+
+    /// Jumps as far towards the board of the session as its server and station
+    /// still allow, with a warning when one of them is not found. Server codes
+    /// and station names or codes are matched regardless of case.
+    pub async fn resume(&mut self, session: Session) {
+        let Some(server) = session.server else {
+            return;
+        };
+        let Some(server_index) = self
+            .visible_servers()
+            .iter()
+            .position(|s| s.server_code.eq_ignore_ascii_case(&server) && s.is_active)
+        else {
+            self.set_status(format!("Server {server} is unknown or inactive"));
+            return;
+        };
+
+        self.server_index = server_index;
+        self.select();
+        // An unreachable API must not stop the app from starting: the station
+        // list is left to the retries of the main loop, as after any failed
+        // refresh, and only the station is not restored.
+        if let Err(err) = self.refresh_data().await {
+            log::error!("refresh failed while resuming the session: {err}");
+            self.refresh_error = Some(err.to_string());
+            if let Some(station) = session.station {
+                self.set_status(format!("Station {station} could not be restored"));
+            }
+            return;
+        }
+
+        let Some(station) = session.station else {
+            return;
+        };
+        match self.stations.iter().position(|s| {
+            s.name.eq_ignore_ascii_case(&station) || s.prefix.eq_ignore_ascii_case(&station)
+        }) {
+            Some(station_index) => {
+                self.station_index = station_index;
+                self.select();
+            }
+            None => {
+                let status = format!("Station {station} not found on {}", self.selected_server);
+                self.set_status(status);
+            }
+        }
+    }
+
+    pub fn filters(&self) -> Filters {
+        Filters {
+            show_bots: self.show_bots,
+            stopping_only: self.stopping_only,
+            time_window: self.time_window,
+            category: self.category_filter,
+            group_by_line: self.group_by_line,
+            view: self.view,
+            sort: self.sort,
+            active_servers_only: self.active_servers_only,
+            group_servers_by_region: self.group_servers_by_region,
+        }
+    }
+
+    pub fn apply_filters(&mut self, filters: Filters) {
+        self.show_bots = filters.show_bots;
+        self.stopping_only = filters.stopping_only;
+        self.time_window = filters.time_window;
+        self.category_filter = filters.category;
+        self.group_by_line = filters.group_by_line;
+        self.view = filters.view;
+        self.sort = filters.sort;
+        self.update_servers(|state| {
+            state.active_servers_only = filters.active_servers_only;
+            state.group_servers_by_region = filters.group_servers_by_region;
+        });
+        self.show_board();
+    }
+
+    /// Servers listed in the server selection, `server_index` points into this list.
+    pub fn visible_servers(&self) -> Vec<&Server> {
+        let mut servers: Vec<&Server> = self
+            .servers
+            .iter()
+            .filter(|s| !self.active_servers_only || s.is_active)
+            .collect();
+        if self.group_servers_by_region {
+            let mut regions = vec![];
+            for server in &servers {
+                let region = server.region();
+                if !regions.contains(&region) {
+                    regions.push(region);
+                }
+            }
+            servers.sort_by_key(|s| {
+                let region = s.region();
+                regions.iter().position(|r| *r == region)
+            });
+        }
+        servers
+    }
+
+    /// Changes how the servers are listed, the selected server stays selected
+    /// when it is still listed.
+    fn update_servers(&mut self, update: impl FnOnce(&mut State)) {
+        let selected = self
+            .visible_servers()
+            .get(self.server_index)
+            .map(|s| s.server_code.clone());
+        update(self);
+        let servers = self.visible_servers();
+        self.server_index = selected
+            .and_then(|code| servers.iter().position(|s| s.server_code == code))
+            .unwrap_or(0)
+            .min(servers.len().saturating_sub(1));
+    }
+
+    pub fn session(&self) -> Session {
+        Session {
+            server: match self.step {
+                Step::ServerSelection => None,
+                _ => Some(self.selected_server.clone()),
+            },
+            station: match self.step {
+                Step::EDR => self.selected_station.as_ref().map(|s| s.name.clone()),
+                _ => None,
+            },
+        }
     }
 
     pub async fn refresh_data(&mut self) -> crate::Result<()> {
-        match self.step {
-            Step::ServerSelection => {
-                self.servers = get_servers().await?;
+        // Past the server selection the list only feeds the offline check, an
+        // outage of it must not stop the stations and the board from refreshing.
+        match get_servers(&self.client, &self.api).await {
+            Ok(servers) => self.servers = servers,
+            Err(err) if !matches!(self.step, Step::ServerSelection) => {
+                log::warn!("server list not refreshed: {err}");
             }
+            Err(err) => return Err(err),
+        }
+        self.server_index = self
+            .server_index
+            .min(self.visible_servers().len().saturating_sub(1));
+
+        // The board is meaningless without a station, fall back to picking one.
+        if matches!(self.step, Step::EDR) && self.selected_station.is_none() {
+            self.step = Step::StationSelection;
+        }
+
+        match self.step {
+            Step::ServerSelection => {}
 
             Step::StationSelection => {
-                self.stations = reqwest::get(format!(
-                    "https://panel.simrail.eu:8084/stations-open?serverCode={}",
-                    self.selected_server
-                ))
-                .await?
-                .json::<StationResponse>()
-                .await?
-                .data;
-
-                self.stations.sort_by(|a, b| a.name.cmp(&b.name));
-
-                let player = self
-                    .stations
-                    .iter()
-                    .flat_map(|s| &s.dispatched_by)
-                    .map(|by| by.steam_id.clone())
-                    .collect::<Vec<_>>();
-
-                if !player.is_empty() {
-                    self.players = reqwest::get(format!(
-                        "https://panel.simrail.eu:8084/users-open/{}",
-                        player.join(",")
-                    ))
-                    .await?
-                    .json::<SteamPlayers>()
+                self.check_server();
+
+                self.stations = self
+                    .api
+                    .get_json::<StationResponse>(
+                        &self.client,
+                        self.api.stations_url(&self.selected_server),
+                    )
                     .await?
-                    .players;
+                    .data;
+
+                self.sort_stations();
+                self.clamp_station_index();
+                self.locator =
+                    StationLocator::new(&self.stations, self.config.max_station_distance_km);
+
+                let unlocated = self.stations.iter().filter(|s| !s.has_position()).count();
+                if unlocated != self.unlocated_stations {
+                    self.unlocated_stations = unlocated;
+                    if unlocated > 0 {
+                        self.set_status(format!(
+                            "{unlocated} stations have no coordinates and are ignored when locating trains"
+                        ));
+                    }
                 }
+
+                let player = self.dispatcher_ids();
+                self.resolve_players(&player).await;
             }
             Step::EDR => {
-                self.events.clear();
-                let mut trains: Vec<Train> = reqwest::get(format!(
-                    "https://panel.simrail.eu:8084/trains-open?serverCode={}",
-                    self.selected_server
-                ))
-                .await?
-                .json::<TrainResponse>()
-                .await?
-                .data;
-
-                for train in trains.iter_mut() {
-                    if let Some((nearest_station, _)) = self
-                        .stations
-                        .iter()
-                        .map(|s| (s, train.dist_from(s)))
-                        .reduce(|(sa, d1), (sb, d2)| match d1.total_cmp(&d2) {
-                            core::cmp::Ordering::Less => (sa, d1),
-                            core::cmp::Ordering::Equal => (sa, d1),
-                            core::cmp::Ordering::Greater => (sb, d2),
-                        })
-                    {
-                        let loc = nearest_station.name.clone();
-                        train.loc = Some(loc.clone());
-
-                        let mut timetable: Vec<StopDescription> = reqwest::get(format!(
-                            "https://simrail-edr.emeraldnetwork.xyz/train/{}/{}",
-                            self.selected_server, train.train_no,
-                        ))
-                        .await?
-                        .json()
-                        .await?;
-
-                        timetable.sort_by(|a, b| a.indexOfPoint.cmp(&b.indexOfPoint));
-
-                        if let Some(train_pos) = timetable.iter().position(|s| s.nameOfPoint == loc)
-                        {
-                            if let Some(station_pos) = timetable.iter().position(|s| {
-                                s.nameOfPoint
-                                    == self
-                                        .selected_station
-                                        .as_ref()
-                                        .expect("no station selected")
-                                        .name
-                            }) {
-                                if train_pos <= station_pos {
-                                    let stop = &timetable[station_pos];
-                                    let next_stop = if station_pos + 1 != timetable.len() {
-                                        &timetable[station_pos + 1]
-                                    } else {
-                                        //todo something better
-                                        &timetable[station_pos]
-                                    };
-                                    let prev_stop = if station_pos != 0 {
-                                        &timetable[station_pos - 1]
-                                    } else {
-                                        //todo something better
-                                        &timetable[station_pos]
-                                    };
-
-                                    if stop.plannedStop.unwrap_or_default() == 0 {
-                                        self.events.push(Event {
-                                            name: format!(
-                                                "{} {}",
-                                                train.train_name, train.train_no
-                                            ),
-                                            time: stop
-                                                .actualArrivalTime
-                                                .as_ref()
-                                                .map(|_| stop.actualArrivalObject),
-                                            planned_time: stop.scheduledArrivalObject,
-                                            ty: EventType::Passing,
-                                            player: train.t != "bot",
-                                            prev: format!(
-                                                "{}/L.{}",
-                                                prev_stop.nameOfPoint, prev_stop.line
-                                            ),
-                                            next: format!(
-                                                "{}/L.{}",
-                                                next_stop.nameOfPoint, stop.line
-                                            ),
-                                        })
-                                    } else {
-                                        self.events.push(Event {
-                                            name: format!(
-                                                "{} {}",
-                                                train.train_name, train.train_no
-                                            ),
-                                            time: stop
-                                                .actualArrivalTime
-                                                .as_ref()
-                                                .map(|_| stop.actualArrivalObject),
-                                            planned_time: stop.scheduledArrivalObject,
-                                            ty: EventType::Entering,
-                                            player: train.t != "bot",
-                                            prev: format!(
-                                                "{}/L.{}",
-                                                prev_stop.nameOfPoint, prev_stop.line
-                                            ),
-                                            next: if let (Some(platform), Some(track)) =
-                                                (stop.platform.as_ref(), stop.track)
-                                            {
-                                                format!("{}/{}", platform, track)
-                                            } else {
-                                                String::from("Not a plaform stop!")
-                                            },
-                                        });
-                                        self.events.push(Event {
-                                            name: format!(
-                                                "{} {}",
-                                                train.train_name, train.train_no
-                                            ),
-                                            time: stop
-                                                .actualDepartureTime
-                                                .as_ref()
-                                                .map(|_| stop.actualDepartureObject),
-                                            planned_time: stop.scheduledDepartureObject,
-                                            ty: EventType::Departing,
-                                            player: train.t != "bot",
-                                            prev: if let (Some(platform), Some(track)) =
-                                                (stop.platform.as_ref(), stop.track)
-                                            {
-                                                format!("{}/{}", platform, track)
-                                            } else {
-                                                String::from("")
-                                            },
-                                            next: format!(
-                                                "{}/L.{}",
-                                                next_stop.nameOfPoint, next_stop.line
-                                            ),
-                                        });
-                                    }
-                                }
+                self.check_server();
+
+                let mut trains: Vec<Train> = self
+                    .api
+                    .get_json::<TrainResponse>(
+                        &self.client,
+                        self.api.trains_url(&self.selected_server),
+                    )
+                    .await?
+                    .data;
+
+                let mut boards: HashMap<String, Vec<Event>> = self
+                    .tabs
+                    .iter()
+                    .chain(&self.combined)
+                    .map(|name| (name.clone(), vec![]))
+                    .collect();
+
+                for train in trains.iter_mut().filter(|t| t.has_position()) {
+                    train.loc = self
+                        .locator
+                        .nearest(train.train_data.latitude, train.train_data.longitude)
+                        .map(String::from);
+                }
+
+                let unlocated = trains.iter().filter(|t| !t.has_position()).count();
+                if unlocated != self.unlocated_trains {
+                    self.unlocated_trains = unlocated;
+                    if unlocated > 0 {
+                        self.set_status(format!(
+                            "{unlocated} trains have no coordinates and are skipped"
+                        ));
+                    }
+                }
+
+                // Without coordinates the position in the timetable cannot be
+                // trusted either, such trains are left off the boards.
+                let located: Vec<&Train> = trains
+                    .iter()
+                    .filter(|t| t.has_position())
+                    .filter(|t| t.loc.is_some() || t.timetable_index().is_some())
+                    .collect();
+                let timetables: Vec<crate::Result<Vec<StopDescription>>> = stream::iter(&located)
+                    .map(|train| {
+                        fetch_timetable(
+                            &self.client,
+                            &self.api,
+                            self.api
+                                .timetable_url(&self.selected_server, &train.train_no),
+                        )
+                    })
+                    .buffered(TIMETABLE_FETCH_CONCURRENCY)
+                    .collect()
+                    .await;
+
+                let known_stations: HashMap<&str, &Station> = self
+                    .stations
+                    .iter()
+                    .filter(|s| s.has_position())
+                    .map(|s| (s.name.as_str(), s))
+                    .collect();
+
+                // A failed timetable only costs that train its fresh data: the
+                // last known timetable is kept and the rest of the board loads.
+                let mut failed = 0;
+                let mut timetable_map = HashMap::new();
+                for (train, timetable) in located.into_iter().zip(timetables) {
+                    let mut timetable = match timetable {
+                        Ok(timetable) => timetable,
+                        Err(_) => {
+                            failed += 1;
+                            match self.timetables.remove(&train.train_no) {
+                                Some(timetable) => timetable,
+                                None => continue,
                             }
                         }
+                    };
+                    timetable.sort_by_key(|s| s.index_of_point);
+
+                    if let Some(train_pos) = train_position(train, &timetable) {
+                        for (station, events) in boards.iter_mut() {
+                            events.extend(station_events(
+                                train,
+                                &timetable,
+                                train_pos,
+                                station,
+                                &known_stations,
+                                &mut self.platform_tracker,
+                                &self.config,
+                            ));
+                        }
                     }
+
+                    timetable_map.insert(train.train_no.clone(), timetable);
+                }
+                self.platform_tracker.prune();
+
+                let mut player = self.dispatcher_ids();
+                player.extend(
+                    trains
+                        .iter()
+                        .flat_map(|t| t.train_data.controlled_by_steam_id.clone()),
+                );
+                self.resolve_players(&player).await;
+
+                if failed > 0 {
+                    self.set_status(format!("{failed} trains failed to load"));
                 }
+
+                self.trains = trains;
+                self.timetables = timetable_map;
+                self.boards = boards;
+                self.show_board();
+                self.announce_arrivals();
             }
         }
+
+        self.last_update = Some(Utc::now());
         Ok(())
     }
 
-    pub fn get_player_name(&self, steam_id: Option<&String>) -> Option<&String> {
-        if let Some(steam_id) = steam_id {
-            self.players
+    fn dispatcher_ids(&self) -> Vec<String> {
+        self.stations
+            .iter()
+            .flat_map(|s| &s.dispatched_by)
+            .map(|by| by.steam_id.clone())
+            .collect()
+    }
+
+    /// Fetches the Steam names of the players missing from `player_names`,
+    /// dispatchers and drivers alike, in a single request.
+    pub async fn resolve_players(&mut self, ids: &[String]) {
+        let mut player: Vec<String> = ids
+            .iter()
+            .filter(|id| !self.player_names.contains_key(*id))
+            .cloned()
+            .collect();
+        player.sort();
+        player.dedup();
+
+        if player.is_empty() {
+            return;
+        }
+        // Names are cosmetic: on failure the ids are shown and the lookup is
+        // retried on the next refresh.
+        let players = match self
+            .api
+            .get_json::<SteamPlayers>(&self.client, self.api.users_url(&player))
+            .await
+        {
+            Ok(response) => response.players,
+            Err(err) => {
+                log::warn!("player names not resolved: {err}");
+                return;
+            }
+        };
+
+        self.player_names
+            .extend(players.into_iter().filter_map(|p| {
+                let name = p.steam_info.into_iter().next()?.personaname;
+                Some((p.steam_id, name))
+            }));
+    }
+
+    /// Shows the cached board of the current tab.
+    fn show_board(&mut self) {
+        // The detail view stays on its train while the board is rebuilt.
+        let followed = self
+            .show_detail
+            .then(|| self.selected_event())
+            .flatten()
+            .map(|e| (e.train_no.clone(), e.station.clone(), e.ty.clone()));
+        self.events = if self.tab_index == 0 && !self.combined.is_empty() {
+            self.combined
                 .iter()
-                .find(|p| &p.steam_id == steam_id)
-                .map(|p| &p.steam_info[0].personaname)
+                .filter_map(|name| self.boards.get(name))
+                .flatten()
+                .cloned()
+                .collect()
         } else {
-            None
+            self.tabs
+                .get(self.tab_index)
+                .and_then(|name| self.boards.get(name))
+                .cloned()
+                .unwrap_or_default()
+        };
+        // Every sort below is stable, ties keep the time order.
+        self.events.sort();
+        match self.sort {
+            SortMode::Time => {}
+            SortMode::Delay => self
+                .events
+                .sort_by_key(|e| std::cmp::Reverse(e.delay_minutes().unwrap_or(i64::MIN))),
+            SortMode::Name => self.events.sort_by(|a, b| a.name.cmp(&b.name)),
+        }
+        // Watched trains go first, within their line when grouped so that each
+        // line stays in one run under its header.
+        let watched = self.watched().to_vec();
+        let group_by_line = self.group_by_line;
+        self.events.sort_by_key(|e| {
+            (
+                group_by_line.then_some(e.line),
+                !watched.contains(&e.train_no),
+            )
+        });
+        let followed = followed.and_then(|(train_no, station, ty)| {
+            self.visible_events()
+                .iter()
+                .position(|e| e.train_no == train_no && e.station == station && e.ty == ty)
+        });
+        if let Some(index) = followed {
+            self.edr_index = index;
+        } else if self.follow {
+            self.select_next_event();
+        } else {
+            self.clamp_edr_index();
         }
     }
 
-    pub fn key_pressed(&mut self, key_code: KeyCode) -> (bool, bool) {
-        match key_code {
-            KeyCode::Enter => self.select(),
-            KeyCode::Up => self.cursor(-1),
-            KeyCode::Down => self.cursor(1),
-            KeyCode::Esc => match self.step {
-                Step::ServerSelection => (false, false),
-                Step::StationSelection => {
-                    self.step = Step::ServerSelection;
-                    (true, false)
-                }
-                Step::EDR => {
-                    self.step = Step::StationSelection;
-                    (true, false)
+    /// Selects the first event that is not in the past yet, the last one when
+    /// they all are.
+    fn select_next_event(&mut self) {
+        let now = Utc::now();
+        let events = self.visible_events();
+        self.edr_index = events
+            .iter()
+            .position(|e| e.effective_time() >= now)
+            .unwrap_or(events.len().saturating_sub(1));
+    }
+
+    /// Name of a board tab, the first one lists the merged stations.
+    pub fn board_name(&self, index: usize) -> String {
+        if index == 0 && !self.combined.is_empty() {
+            self.combined.join(" + ")
+        } else {
+            self.tabs.get(index).cloned().unwrap_or_default()
+        }
+    }
+
+    fn cycle_tab(&mut self, i: isize) -> (bool, bool) {
+        if self.tabs.is_empty() {
+            return (false, false);
+        }
+        let index = (self.tab_index as isize + i).rem_euclid(self.tabs.len() as isize);
+        self.switch_tab(index as _)
+    }
+
+    fn switch_tab(&mut self, index: usize) -> (bool, bool) {
+        let Some(name) = self.tabs.get(index) else {
+            return (false, false);
+        };
+        let Some(station) = self.stations.iter().find(|s| &s.name == name) else {
+            return (false, false);
+        };
+
+        self.selected_station = Some(station.clone());
+        self.tab_index = index;
+        self.edr_index = 0;
+        self.show_detail = false;
+        self.show_board();
+        (false, true)
+    }
+
+    /// Events passing the board filters, `edr_index` points into this list.
+    pub fn visible_events(&self) -> Vec<&Event> {
+        let query = self.train_query.to_lowercase();
+        self.events
+            .iter()
+            .filter(|e| e.name.to_lowercase().contains(&query))
+            .filter(|e| self.show_bots || e.player)
+            .filter(|e| !self.stopping_only || e.ty != EventType::Passing)
+            .filter(|e| self.view.shows(&e.ty))
+            .filter(|e| self.category_filter.is_none_or(|c| e.category == c))
+            .filter(|e| match self.time_window {
+                Some((before, after)) => {
+                    let offset = (e.effective_time() - Utc::now()).num_minutes();
+                    (-before..=after).contains(&offset)
                 }
-            },
-            _ => (false, false),
+                None => true,
+            })
+            .collect()
+    }
+
+    fn clamp_edr_index(&mut self) {
+        let visible = self.visible_events().len();
+        if self.edr_index >= visible {
+            self.edr_index = visible.saturating_sub(1);
         }
     }
 
-    fn select(&mut self) -> (bool, bool) {
-        match self.step {
-            Step::ServerSelection => {
-                self.selected_server = self.servers[self.server_index].server_code.clone();
-                self.step = Step::StationSelection;
-                (true, true)
-            }
-            Step::StationSelection => {
-                self.selected_station = Some(self.stations[self.station_index].clone());
-                self.step = Step::EDR;
-                (true, true)
-            }
-            Step::EDR => (false, false),
+    pub fn selected_event(&self) -> Option<&Event> {
+        self.visible_events().get(self.edr_index).copied()
+    }
+
+    pub fn selected_timetable(&self) -> &[StopDescription] {
+        self.selected_event()
+            .and_then(|event| self.timetables.get(&event.train_no))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Station of an event, which is not the selected one on combined boards.
+    pub fn event_station(&self, event: &Event) -> Option<&Station> {
+        self.stations.iter().find(|s| s.name == event.station)
+    }
+
+    pub fn selected_train(&self) -> Option<&Train> {
+        let event = self.selected_event()?;
+        self.trains.iter().find(|t| t.train_no == event.train_no)
+    }
+
+    pub fn banner(&self) -> Option<String> {
+        if self.server_offline {
+            Some(format!(
+                " Server {} went offline - press Esc to return to server selection ",
+                self.selected_server
+            ))
+        } else if let Some(err) = self.refresh_error.as_ref() {
+            Some(format!(" Last refresh failed, retrying... ({err}) "))
+        } else if self.refresh_overrun {
+            Some(String::from(
+                " Refreshing takes longer than the refresh interval, skipping a tick ",
+            ))
+        } else {
+            None
         }
     }
 
-    fn cursor(&mut self, i: isize) -> (bool, bool) {
-        match self.step {
-            Step::ServerSelection => {
-                let mut res = (self.server_index as isize) + i;
-                if res < 0 {
-                    res = (self.servers.len() - 1) as _;
-                } else if res >= self.servers.len() as isize {
-                    res = 0;
+    fn export(&mut self, export: fn(&State) -> crate::Result<PathBuf>) -> (bool, bool) {
+        let status = match export(self) {
+            Ok(path) => format!("Board exported to {}", path.display()),
+            Err(err) => format!("Export failed: {err}"),
+        };
+        self.set_status(status);
+        (false, true)
+    }
+
+    /// Copies the train number of the selected event to the clipboard.
+    fn yank(&mut self) -> (bool, bool) {
+        let Some(train_no) = self.selected_event().map(|e| e.train_no.clone()) else {
+            return (false, false);
+        };
+        let copied = match self.clipboard.take() {
+            Some(clipboard) => Ok(clipboard),
+            None => arboard::Clipboard::new(),
+        }
+        .and_then(|mut clipboard| {
+            clipboard.set_text(train_no.clone())?;
+            self.clipboard = Some(clipboard);
+            Ok(())
+        });
+        let status = match copied {
+            Ok(()) => format!("Copied {train_no}"),
+            Err(err) => format!("Copy failed: {err}"),
+        };
+        self.set_status(status);
+        (false, true)
+    }
+
+    pub fn set_status(&mut self, status: String) {
+        self.status = Some(status);
+        self.status_since = Instant::now();
+    }
+
+    /// Time left before the status message is cleared.
+    pub fn status_expires_in(&self) -> Option<Duration> {
+        self.status.as_ref()?;
+        Some(
+            Duration::from_secs(self.config.status_secs)
+                .saturating_sub(self.status_since.elapsed()),
+        )
+    }
+
+    /// Clears the status message once it timed out, returns whether it did.
+    pub fn expire_status(&mut self) -> bool {
+        if self.status_expires_in() == Some(Duration::ZERO) {
+            self.status = None;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn check_server(&mut self) {
+        self.server_offline = !self
+            .servers
+            .iter()
+            .any(|s| s.server_code == self.selected_server && s.is_active);
+    }
+
+    /// Text of the board columns of an event, shared by the table and the exports.
+    /// Titles of the board columns, matching `board_columns`.
+    pub fn board_header(&self) -> [String; BOARD_HEADER.len()] {
+        BOARD_HEADER.map(|title| match title {
+            "Time" if self.split_times => String::from("Sched"),
+            "Delay" if self.split_times => String::from("Act"),
+            _ => title.to_string(),
+        })
+    }
+
+    /// The columns of the board as last drawn, which the exports follow.
+    pub fn board_layout(&self) -> BoardLayout<'_> {
+        BoardLayout {
+            columns: &self.config.columns,
+            combined: !self.combined.is_empty(),
+            compact: self.compact_board,
+        }
+    }
+
+    pub fn board_columns(&self, e: &Event) -> [String; BOARD_HEADER.len()] {
+        [
+            format!(
+                "{}{}",
+                if e.player { '*' } else { ' ' },
+                if self.is_late(e) { '!' } else { ' ' }
+            ),
+            {
+                let mut name = e.name.clone();
+                if let Some(visit) = e.visit {
+                    name.push_str(&format!(" #{visit}"));
                 }
-                self.server_index = res as _;
-                (false, true)
-            }
-            Step::StationSelection => {
-                let mut res = (self.station_index as isize) + i;
-                if res < 0 {
-                    res = (self.stations.len() - 1) as _;
-                } else if res >= self.stations.len() as isize {
-                    res = 0;
+                if let Some(driver) = self.get_player_name(e.driver.as_ref()) {
+                    name.push_str(&format!(" ({driver})"));
                 }
-                self.station_index = res as _;
-                (false, true)
-            }
-            Step::EDR => (false, false),
+                name
+            },
+            e.category.tag().to_string(),
+            e.ty.label().to_string(),
+            e.station.clone(),
+            // Split times show the scheduled and the actual time instead.
+            if self.split_times {
+                self.config.format_time(&e.planned_time)
+            } else {
+                e.get_time(&self.config)
+            },
+            if self.split_times {
+                e.time
+                    .map(|time| self.config.format_time(&time))
+                    .unwrap_or_default()
+            } else {
+                e.get_delay()
+            },
+            self.eta_column(e),
+            e.platform.clone().unwrap_or_default(),
+            format!("{} → {}", e.start, e.end),
+            e.prev.clone(),
+            e.next.clone(),
+        ]
+    }
+
+    /// The estimated arrival of a player train on its way, "—" when it is
+    /// stopped. Empty for bots, departures and trains already there.
+    fn eta_column(&self, e: &Event) -> String {
+        if !e.player || e.ty == EventType::Departing || e.time.is_some() {
+            return String::new();
         }
+        e.eta
+            .map(|eta| self.config.format_time(&eta))
+            .unwrap_or_else(|| String::from("—"))
     }
-}
 
-async fn get_servers() -> crate::Result<Vec<Server>> {
-    let servers = reqwest::get("https://panel.simrail.eu:8084/servers-open")
-        .await?
-        .json::<ServerResponse>()
-        .await?
-        .data;
-    Ok(servers)
+    /// Whether the event is delayed beyond the configured alert threshold.
+    pub fn is_late(&self, e: &Event) -> bool {
+        e.delay_minutes()
+            .is_some_and(|delay| delay >= self.config.delay_alert_mins)
+    }
+
+    pub fn get_player_name(&self, steam_id: Option<&String>) -> Option<&String> {
+        self.player_names.get(steam_id?)
+    }
+
+    /// Favorites first, then the dispatched stations if requested, each group
+    /// in alphabetical order.
+    fn sort_stations(&mut self) {
+        let dispatched_first = self.dispatched_first;
+        let favorites = self
+            .favorites
+            .get(&self.selected_server)
+            .cloned()
+            .unwrap_or_default();
+        self.stations.sort_by_cached_key(|s| {
+            (
+                !favorites.contains(&s.name),
+                dispatched_first && s.dispatched_by.is_empty(),
+                s.name.clone(),
+            )
+        });
+    }
+
+    pub fn is_favorite(&self, station: &Station) -> bool {
+        self.favorites
+            .get(&self.selected_server)
+            .is_some_and(|names| names.contains(&station.name))
+    }
+
+    /// Rings the bell when a watched train reaches one of the stations it
+    /// enters on the boards.
+    fn announce_arrivals(&mut self) {
+        if !self.config.notify_watched {
+            return;
+        }
+        let arriving: HashMap<&str, &str> = self
+            .boards
+            .values()
+            .flatten()
+            .filter(|e| e.ty == EventType::Entering && self.watched().contains(&e.train_no))
+            .filter(|e| {
+                self.trains
+                    .iter()
+                    .any(|t| t.train_no == e.train_no && t.loc.as_ref() == Some(&e.station))
+            })
+            .map(|e| (e.train_no.as_str(), e.name.as_str()))
+            .collect();
+
+        let new: Vec<&str> = arriving
+            .iter()
+            .filter(|(train_no, _)| !self.arrived.contains(**train_no))
+            .map(|(_, name)| *name)
+            .collect();
+        let status = (!new.is_empty()).then(|| format!("Arriving: {}", new.join(", ")));
+        self.arrived = arriving.keys().map(|no| no.to_string()).collect();
+
+        if let Some(status) = status {
+            self.bell = true;
+            self.set_status(status);
+        }
+    }
+
+    pub fn take_bell(&mut self) -> bool {
+        std::mem::take(&mut self.bell)
+    }
+
+    /// Numbers of the trains watched on the selected server.
+    pub fn watched(&self) -> &[String] {
+        self.watchlist
+            .get(&self.selected_server)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    pub fn is_watched(&self, event: &Event) -> bool {
+        self.watched().contains(&event.train_no)
+    }
+
+    fn toggle_watched(&mut self) -> (bool, bool) {
+        let Some(train_no) = self.selected_event().map(|e| e.train_no.clone()) else {
+            return (false, false);
+        };
+
+        let watched = self
+            .watchlist
+            .entry(self.selected_server.clone())
+            .or_default();
+        match watched.iter().position(|n| *n == train_no) {
+            Some(index) => {
+                watched.remove(index);
+            }
+            None => watched.push(train_no.clone()),
+        }
+        if let Err(err) = persist::save(persist::WATCHLIST_FILE, &self.watchlist) {
+            self.set_status(format!("Saving the watchlist failed: {err}"));
+        }
+        self.show_board();
+        // Keeps the cursor on the train now that it moved.
+        if let Some(index) = self
+            .visible_events()
+            .iter()
+            .position(|e| e.train_no == train_no)
+        {
+            self.edr_index = index;
+        }
+        (false, true)
+    }
+
+    fn toggle_favorite(&mut self) -> (bool, bool) {
+        let Some(name) = self
+            .visible_stations()
+            .get(self.station_index)
+            .map(|s| s.name.clone())
+        else {
+            return (false, false);
+        };
+
+        let names = self
+            .favorites
+            .entry(self.selected_server.clone())
+            .or_default();
+        match names.iter().position(|n| *n == name) {
+            Some(index) => {
+                names.remove(index);
+            }
+            None => names.push(name.clone()),
+        }
+        if let Err(err) = persist::save(persist::FAVORITES_FILE, &self.favorites) {
+            self.set_status(format!("Saving favorites failed: {err}"));
+        }
+        self.sort_stations();
+        self.reselect_station(&name);
+        (false, true)
+    }
+
+    /// Keeps the cursor on the named station after the list was reordered.
+    fn reselect_station(&mut self, name: &str) {
+        if let Some(index) = self.visible_stations().iter().position(|s| s.name == name) {
+            self.station_index = index;
+        }
+        self.clamp_station_index();
+    }
+
+    fn clamp_station_index(&mut self) {
+        let visible = self.visible_stations().len();
+        if self.station_index >= visible {
+            self.station_index = visible.saturating_sub(1);
+        }
+    }
+
+    /// Stations matching the search query, `station_index` points into this list.
+    pub fn visible_stations(&self) -> Vec<&Station> {
+        let query = self.station_query.to_lowercase();
+        self.stations
+            .iter()
+            .filter(|s| {
+                s.name.to_lowercase().contains(&query) || s.prefix.to_lowercase().contains(&query)
+            })
+            .filter(|s| !self.favorites_only || self.is_favorite(s))
+            .collect()
+    }
+
+    /// Moves the selection to the first station whose prefix starts with the
+    /// characters typed in quick succession. Lowercase letters stay shortcuts,
+    /// so the codes are typed in uppercase.
+    fn type_ahead(&mut self, c: char) -> (bool, bool) {
+        if self.prefix_typed_at.elapsed() > TYPE_AHEAD_IDLE {
+            self.prefix_typed.clear();
+        }
+        self.prefix_typed.push(c);
+        self.prefix_typed_at = Instant::now();
+
+        let typed = self.prefix_typed.to_lowercase();
+        if let Some(index) = self
+            .visible_stations()
+            .iter()
+            .position(|s| s.prefix.to_lowercase().starts_with(&typed))
+        {
+            self.station_index = index;
+        }
+        self.set_status(format!("Jump to {}", self.prefix_typed));
+        (false, true)
+    }
+
+    /// Whether keys are currently typed into a text field rather than used as shortcuts.
+    pub fn is_typing(&self) -> bool {
+        self.searching
+    }
+
+    pub fn key_pressed(&mut self, key_code: KeyCode) -> (bool, bool) {
+        if self.show_help {
+            self.show_help = false;
+            return (false, true);
+        }
+        if self.searching {
+            return self.search_key(key_code);
+        }
+        if self.show_overview && matches!(self.step, Step::StationSelection) {
+            return match key_code {
+                KeyCode::Char('o') | KeyCode::Esc => {
+                    self.show_overview = false;
+                    (false, true)
+                }
+                KeyCode::Char('r') => (true, true),
+                _ => (false, false),
+            };
+        }
+
+        match key_code {
+            KeyCode::Enter => self.select(),
+            KeyCode::Char('?') => {
+                self.show_help = true;
+                (false, true)
+            }
+            KeyCode::Char('r') => (true, true),
+            KeyCode::Up | KeyCode::Char('k') => self.cursor(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.cursor(1),
+            KeyCode::PageUp => self.page(-1),
+            KeyCode::PageDown => self.page(1),
+            KeyCode::Home => self.jump(false),
+            KeyCode::End => self.jump(true),
+            KeyCode::Tab if matches!(self.step, Step::EDR) => self.cycle_tab(1),
+            KeyCode::BackTab if matches!(self.step, Step::EDR) => self.cycle_tab(-1),
+            KeyCode::Char(c @ '1'..='9') if matches!(self.step, Step::EDR) => {
+                self.switch_tab(c as usize - '1' as usize)
+            }
+            KeyCode::Char('b') if matches!(self.step, Step::EDR) => {
+                self.show_bots = !self.show_bots;
+                self.clamp_edr_index();
+                (false, true)
+            }
+            KeyCode::Char('s') if matches!(self.step, Step::EDR) => {
+                self.stopping_only = !self.stopping_only;
+                self.clamp_edr_index();
+                (false, true)
+            }
+            KeyCode::Char('o') if matches!(self.step, Step::EDR) => {
+                self.sort = self.sort.next();
+                self.show_board();
+                (false, true)
+            }
+            KeyCode::Char('v') if matches!(self.step, Step::EDR) => {
+                self.view = self.view.next();
+                self.clamp_edr_index();
+                (false, true)
+            }
+            KeyCode::Char('c') if matches!(self.step, Step::EDR) => {
+                // All categories, then each one in turn.
+                self.category_filter = match self.category_filter {
+                    None => Some(TrainCategory::ALL[0]),
+                    Some(current) => TrainCategory::ALL
+                        .iter()
+                        .skip_while(|c| **c != current)
+                        .nth(1)
+                        .copied(),
+                };
+                self.clamp_edr_index();
+                (false, true)
+            }
+            KeyCode::Char('t') if matches!(self.step, Step::EDR) => {
+                self.split_times = !self.split_times;
+                (false, true)
+            }
+            KeyCode::Char('w') if matches!(self.step, Step::EDR) => {
+                self.time_window = match self.time_window {
+                    Some(_) => None,
+                    None => Some((
+                        self.config.window_before_mins,
+                        self.config.window_after_mins,
+                    )),
+                };
+                self.clamp_edr_index();
+                (false, true)
+            }
+            KeyCode::Char('f') if matches!(self.step, Step::EDR) => {
+                self.follow = !self.follow;
+                if self.follow {
+                    self.select_next_event();
+                }
+                (false, true)
+            }
+            KeyCode::Char('g') if matches!(self.step, Step::EDR) => {
+                self.group_by_line = !self.group_by_line;
+                self.show_board();
+                (false, true)
+            }
+            KeyCode::Char('e') if matches!(self.step, Step::EDR) => self.export(export::csv),
+            KeyCode::Char('y') if matches!(self.step, Step::EDR) => self.yank(),
+            KeyCode::Char('p') if matches!(self.step, Step::EDR) => self.toggle_watched(),
+            KeyCode::Char('d') if matches!(self.step, Step::StationSelection) => {
+                let selected = self
+                    .visible_stations()
+                    .get(self.station_index)
+                    .map(|s| s.name.clone())
+                    .unwrap_or_default();
+                self.dispatched_first = !self.dispatched_first;
+                self.sort_stations();
+                self.reselect_station(&selected);
+                (false, true)
+            }
+            KeyCode::Char(' ') if matches!(self.step, Step::StationSelection) => {
+                let Some(name) = self
+                    .visible_stations()
+                    .get(self.station_index)
+                    .map(|s| s.name.clone())
+                else {
+                    return (false, false);
+                };
+                match self.marked_stations.iter().position(|n| *n == name) {
+                    Some(index) => {
+                        self.marked_stations.remove(index);
+                    }
+                    None => self.marked_stations.push(name),
+                }
+                (false, true)
+            }
+            KeyCode::Char('f') if matches!(self.step, Step::StationSelection) => {
+                self.toggle_favorite()
+            }
+            KeyCode::Char('o') if matches!(self.step, Step::StationSelection) => {
+                self.show_overview = true;
+                (false, true)
+            }
+            KeyCode::Char('a') if matches!(self.step, Step::ServerSelection) => {
+                self.update_servers(|state| state.active_servers_only = !state.active_servers_only);
+                (false, true)
+            }
+            KeyCode::Char('g') if matches!(self.step, Step::ServerSelection) => {
+                self.update_servers(|state| {
+                    state.group_servers_by_region = !state.group_servers_by_region
+                });
+                (false, true)
+            }
+            KeyCode::Char('*') if matches!(self.step, Step::StationSelection) => {
+                self.favorites_only = !self.favorites_only;
+                self.station_index = 0;
+                (false, true)
+            }
+            KeyCode::Char('J') if matches!(self.step, Step::EDR) => self.export(export::json),
+            KeyCode::Char('/')
+                if !matches!(self.step, Step::ServerSelection) && !self.show_detail =>
+            {
+                self.searching = true;
+                (false, true)
+            }
+            KeyCode::Char(c)
+                if matches!(self.step, Step::StationSelection)
+                    && (c.is_ascii_uppercase() || c.is_ascii_digit()) =>
+            {
+                self.type_ahead(c)
+            }
+            KeyCode::Esc
+                if matches!(self.step, Step::StationSelection) && !self.prefix_typed.is_empty() =>
+            {
+                self.prefix_typed.clear();
+                self.status = None;
+                (false, true)
+            }
+            KeyCode::Esc
+                if matches!(self.step, Step::StationSelection)
+                    && !self.marked_stations.is_empty() =>
+            {
+                self.marked_stations.clear();
+                (false, true)
+            }
+            KeyCode::Esc
+                if matches!(self.step, Step::StationSelection)
+                    && !self.station_query.is_empty() =>
+            {
+                self.clear_search()
+            }
+            KeyCode::Esc if self.show_detail => {
+                self.show_detail = false;
+                (false, true)
+            }
+            KeyCode::Esc if matches!(self.step, Step::EDR) && !self.train_query.is_empty() => {
+                self.clear_search()
+            }
+            KeyCode::Char('S') if matches!(self.step, Step::EDR) => {
+                self.selected_station = None;
+                self.show_detail = false;
+                self.train_query.clear();
+                self.server_offline = false;
+                self.edr_index = 0;
+                self.step = Step::ServerSelection;
+                (true, true)
+            }
+            KeyCode::Esc if self.server_offline => {
+                self.server_offline = false;
+                self.step = Step::ServerSelection;
+                (true, false)
+            }
+            KeyCode::Esc => match self.step {
+                Step::ServerSelection => (false, false),
+                Step::StationSelection => {
+                    self.step = Step::ServerSelection;
+                    (true, false)
+                }
+                Step::EDR => {
+                    self.step = Step::StationSelection;
+                    self.edr_index = 0;
+                    (true, false)
+                }
+            },
+            _ => (false, false),
+        }
+    }
+
+    fn search_key(&mut self, key_code: KeyCode) -> (bool, bool) {
+        match key_code {
+            KeyCode::Char(c) => {
+                let (query, index) = self.search_query();
+                query.push(c);
+                *index = 0;
+                (false, true)
+            }
+            KeyCode::Backspace => {
+                let (query, index) = self.search_query();
+                query.pop();
+                *index = 0;
+                (false, true)
+            }
+            KeyCode::Up => self.cursor(-1),
+            KeyCode::Down => self.cursor(1),
+            // The board keeps its filter, a station is picked right away.
+            KeyCode::Enter if matches!(self.step, Step::EDR) => {
+                self.searching = false;
+                (false, true)
+            }
+            KeyCode::Enter => {
+                self.searching = false;
+                self.select()
+            }
+            KeyCode::Esc => self.clear_search(),
+            _ => (false, false),
+        }
+    }
+
+    fn clear_search(&mut self) -> (bool, bool) {
+        self.searching = false;
+        let (query, index) = self.search_query();
+        query.clear();
+        *index = 0;
+        (false, true)
+    }
+
+    /// The query typed on the current step and the selection it filters.
+    fn search_query(&mut self) -> (&mut String, &mut usize) {
+        match self.step {
+            Step::EDR => (&mut self.train_query, &mut self.edr_index),
+            _ => (&mut self.station_query, &mut self.station_index),
+        }
+    }
+
+    fn select(&mut self) -> (bool, bool) {
+        match self.step {
+            Step::ServerSelection => {
+                let Some(server) = self.visible_servers().get(self.server_index).copied() else {
+                    return (false, false);
+                };
+                // Inactive servers report no stations nor trains.
+                if !server.is_active {
+                    let message = format!("{} is inactive", server.server_code);
+                    self.set_status(message);
+                    return (false, true);
+                }
+                self.selected_server = server.server_code.clone();
+                self.step = Step::StationSelection;
+                (true, true)
+            }
+            Step::StationSelection => {
+                // Marked stations are merged into one board, headed by the first.
+                let marked = std::mem::take(&mut self.marked_stations);
+                let station = match marked.first() {
+                    Some(name) => self.stations.iter().find(|s| &s.name == name),
+                    None => self.visible_stations().get(self.station_index).copied(),
+                };
+                let Some(station) = station.cloned() else {
+                    return (false, false);
+                };
+                self.combined = if marked.len() > 1 { marked } else { vec![] };
+
+                self.tabs = vec![station.name.clone()];
+                self.tabs.extend(
+                    self.config
+                        .monitored_stations
+                        .iter()
+                        .filter(|name| **name != station.name && !self.combined.contains(name))
+                        .filter(|name| self.stations.iter().any(|s| &s.name == *name))
+                        .cloned(),
+                );
+                self.tab_index = 0;
+                self.boards.clear();
+                self.events.clear();
+
+                self.selected_station = Some(station);
+                self.step = Step::EDR;
+                (true, true)
+            }
+            Step::EDR => {
+                self.show_detail = !self.show_detail && self.selected_event().is_some();
+                if self.show_detail {
+                    self.detail_index = self
+                        .selected_event()
+                        .and_then(|event| {
+                            self.selected_timetable()
+                                .iter()
+                                .position(|s| s.name_of_point == event.station)
+                        })
+                        .unwrap_or_default();
+                }
+                (false, true)
+            }
+        }
+    }
+
+    /// The index and list length navigated by Home/End and paging.
+    fn selection(&mut self) -> Option<(&mut usize, usize)> {
+        let len = match self.step {
+            Step::ServerSelection => self.visible_servers().len(),
+            Step::StationSelection => self.visible_stations().len(),
+            Step::EDR if self.show_detail => self.selected_timetable().len(),
+            Step::EDR => self.visible_events().len(),
+        };
+        if len == 0 {
+            return None;
+        }
+        match self.step {
+            Step::ServerSelection => Some((&mut self.server_index, len)),
+            Step::StationSelection => Some((&mut self.station_index, len)),
+            Step::EDR if self.show_detail => Some((&mut self.detail_index, len)),
+            Step::EDR => {
+                // Scrolling by hand takes over from the follow mode.
+                self.follow = false;
+                Some((&mut self.edr_index, len))
+            }
+        }
+    }
+
+    /// Moves the selection by whole pages, stopping at the ends of the list.
+    fn page(&mut self, pages: isize) -> (bool, bool) {
+        self.page_by(pages * self.page_size as isize)
+    }
+
+    fn page_by(&mut self, rows: isize) -> (bool, bool) {
+        let Some((index, len)) = self.selection() else {
+            return (false, false);
+        };
+
+        let res = *index as isize + rows;
+        *index = res.clamp(0, len as isize - 1) as _;
+        (false, true)
+    }
+
+    fn jump(&mut self, to_end: bool) -> (bool, bool) {
+        let Some((index, len)) = self.selection() else {
+            return (false, false);
+        };
+
+        *index = if to_end { len - 1 } else { 0 };
+        (false, true)
+    }
+
+    /// Moves the selection by one row, wrapping around at the ends of the list
+    /// unless disabled in the config.
+    fn cursor(&mut self, i: isize) -> (bool, bool) {
+        // The timetable scrolls rather than wraps around.
+        if !self.config.wrap_navigation || matches!(self.step, Step::EDR) && self.show_detail {
+            return self.page_by(i);
+        }
+        let Some((index, len)) = self.selection() else {
+            return (false, false);
+        };
+
+        *index = (*index as isize + i).rem_euclid(len as isize) as _;
+        (false, true)
+    }
+}
+
+fn station_events(
+    train: &Train,
+    timetable: &[StopDescription],
+    train_pos: usize,
+    station: &str,
+    known_stations: &HashMap<&str, &Station>,
+    tracker: &mut PlatformTracker,
+    config: &Config,
+) -> Vec<Event> {
+    let mut events = vec![];
+
+    // Turnbacks and reversals call at the same station more than once, each
+    // visit gets its own events.
+    let visits: Vec<usize> = timetable
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| s.name_of_point == station)
+        .map(|(i, _)| i)
+        .collect();
+
+    for (visit, &station_pos) in visits.iter().enumerate() {
+        let visit = (visits.len() > 1).then_some(visit + 1);
+        if train_pos <= station_pos {
+            let stop = &timetable[station_pos];
+            let neighbour = |s: &StopDescription| format!("{}/L.{}", s.name_of_point, s.line);
+            let prev = match station_pos.checked_sub(1) {
+                Some(prev_pos) => neighbour(&timetable[prev_pos]),
+                None => String::from("ORIGIN"),
+            };
+            let next = match timetable.get(station_pos + 1) {
+                Some(next_stop) => neighbour(next_stop),
+                None => String::from("TERM"),
+            };
+            let eta = remaining_km(train, &timetable[train_pos..=station_pos], known_stations)
+                .and_then(|km| eta(km, train.train_data.velocity))
+                .map(|eta| Utc::now() + eta);
+
+            if stop.planned_stop.unwrap_or_default() == 0 {
+                events.push(Event {
+                    name: format!("{} {}", train.train_name, train.train_no),
+                    station: station.to_string(),
+                    time: stop.actual_arrival(),
+                    planned_time: stop.scheduled_arrival_object,
+                    ty: EventType::Passing,
+                    player: train.t != "bot",
+                    driver: train.train_data.controlled_by_steam_id.clone(),
+                    train_no: train.train_no.clone(),
+                    category: TrainCategory::parse(&train.train_name),
+                    start: train.start.clone(),
+                    end: train.end.clone(),
+                    visit,
+                    platform: None,
+                    platform_change: None,
+                    line: stop.line,
+                    eta,
+                    prev,
+                    next,
+                })
+            } else {
+                let platform = stop
+                    .platform
+                    .as_ref()
+                    .zip(stop.track)
+                    .map(|(platform, track)| format!("{}/{}", platform, track));
+                let platform_change = tracker.update(
+                    &train.train_no,
+                    &format!("{}#{}", stop.name_of_point, stop.index_of_point),
+                    platform.as_ref(),
+                    Duration::from_secs(config.platform_change_secs),
+                );
+
+                events.push(Event {
+                    name: format!("{} {}", train.train_name, train.train_no),
+                    station: station.to_string(),
+                    time: stop.actual_arrival(),
+                    planned_time: stop.scheduled_arrival_object,
+                    ty: EventType::Entering,
+                    player: train.t != "bot",
+                    driver: train.train_data.controlled_by_steam_id.clone(),
+                    train_no: train.train_no.clone(),
+                    category: TrainCategory::parse(&train.train_name),
+                    start: train.start.clone(),
+                    end: train.end.clone(),
+                    visit,
+                    platform: platform.clone(),
+                    platform_change: platform_change.clone(),
+                    line: stop.line,
+                    eta,
+                    prev: prev.clone(),
+                    next: next.clone(),
+                });
+                events.push(Event {
+                    name: format!("{} {}", train.train_name, train.train_no),
+                    station: station.to_string(),
+                    time: stop.actual_departure(),
+                    planned_time: stop.scheduled_departure_object,
+                    ty: EventType::Departing,
+                    player: train.t != "bot",
+                    driver: train.train_data.controlled_by_steam_id.clone(),
+                    train_no: train.train_no.clone(),
+                    category: TrainCategory::parse(&train.train_name),
+                    start: train.start.clone(),
+                    end: train.end.clone(),
+                    visit,
+                    platform,
+                    platform_change,
+                    line: stop.line,
+                    eta: None,
+                    prev,
+                    next,
+                });
+            }
+        }
+    }
+
+    events
+}
+
+/// Distance left along the route through the known stations of `stops`, the
+/// last of which is the destination. Points without coordinates are skipped,
+/// down to the straight line to the destination.
+fn remaining_km(
+    train: &Train,
+    stops: &[StopDescription],
+    known_stations: &HashMap<&str, &Station>,
+) -> Option<f32> {
+    // Without the destination, the sum would stop short of it.
+    known_stations.get(stops.last()?.name_of_point.as_str())?;
+    let mut position = (train.train_data.latitude, train.train_data.longitude);
+    let mut km = 0.0;
+    for station in stops
+        .iter()
+        .filter_map(|s| known_stations.get(s.name_of_point.as_str()))
+    {
+        km += distance_km(position.0, position.1, station.latitude, station.longitude);
+        position = (station.latitude, station.longitude);
+    }
+    Some(km)
+}
+
+/// Where the train is in its timetable: the index reported by the simulation
+/// when usable, the stop at the nearest station otherwise.
+///
+/// `VDDelayedTimetableIndex` is the `indexOfPoint` of the entry, it is looked
+/// up rather than used as a position in the list: the list is only sorted by
+/// that index here, and it does not have to start at 0 nor be gapless.
+fn train_position(train: &Train, timetable: &[StopDescription]) -> Option<usize> {
+    train
+        .timetable_index()
+        .and_then(|index| timetable.iter().position(|s| s.index_of_point == index))
+        .or_else(|| {
+            timetable
+                .iter()
+                .position(|s| Some(&s.name_of_point) == train.loc.as_ref())
+        })
+}
+
+async fn fetch_timetable(
+    client: &reqwest::Client,
+    api: &Api,
+    url: String,
+) -> crate::Result<Vec<StopDescription>> {
+    api.get_json(client, url).await
+}
+
+async fn get_servers(client: &reqwest::Client, api: &Api) -> crate::Result<Vec<Server>> {
+    let servers = api
+        .get_json::<ServerResponse>(client, api.servers_url())
+        .await?
+        .data;
+    Ok(servers)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+    use wiremock::matchers::{path, path_regex};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+    use crate::config::TimeFormat;
+
+    const DISPATCHER: &str = "76561198000000001";
+
+    fn servers() -> Value {
+        json!({
+            "result": true,
+            "data": [
+                { "ServerCode": "pl1", "ServerName": "Polski 1", "IsActive": true },
+                { "ServerCode": "en1", "ServerName": "English 1", "IsActive": false },
+            ]
+        })
+    }
+
+    fn stations() -> Value {
+        json!({
+            "result": true,
+            "data": [
+                {
+                    "Name": "Katowice",
+                    "Prefix": "KO",
+                    "DispatchedBy": [{ "SteamId": DISPATCHER }],
+                    "Latititude": 50.2587,
+                    "Longitude": 19.0172,
+                },
+                {
+                    "Name": "Sosnowiec Główny",
+                    "Prefix": "SG",
+                    "DispatchedBy": [],
+                    "Latititude": 50.2776,
+                    "Longitude": 19.1288,
+                },
+            ]
+        })
+    }
+
+    fn users(ids: &[&str]) -> Value {
+        json!({
+            "result": true,
+            "data": ids
+                .iter()
+                .map(|id| json!({ "SteamId": id, "SteamInfo": [{ "personaname": format!("player {id}") }] }))
+                .collect::<Vec<_>>()
+        })
+    }
+
+    async fn mount(server: &MockServer, endpoint: &str, body: Value) {
+        Mock::given(path(endpoint))
+            .respond_with(ResponseTemplate::new(200).set_body_json(body))
+            .mount(server)
+            .await;
+    }
+
+    /// A state talking to `server` only, on the server selection.
+    async fn mock_state(server: &MockServer) -> State {
+        state_with_servers(server, servers()).await
+    }
+
+    async fn state_with_servers(server: &MockServer, servers: Value) -> State {
+        mount(server, "/servers-open", servers).await;
+        let config = Config {
+            panel_url: Some(server.uri()),
+            timetable_url: Some(server.uri()),
+            request_retries: 0,
+            ..Config::default()
+        };
+        let mut state = State::new(config).await.unwrap();
+        // Whatever the user bookmarked must not reorder the lists.
+        state.favorites.clear();
+        state.watchlist.clear();
+        state
+    }
+
+    /// A state on the station selection of pl1, with the stations loaded.
+    async fn station_selection(server: &MockServer) -> State {
+        let mut state = mock_state(server).await;
+        mount(server, "/stations-open", stations()).await;
+        Mock::given(path_regex("^/users-open/"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(users(&[DISPATCHER])))
+            .mount(server)
+            .await;
+        state.select();
+        state.refresh_data().await.unwrap();
+        state
+    }
+
+    fn train(train_no: &str, position: (f32, f32), index: isize, driver: Option<&str>) -> Value {
+        json!({
+            "TrainNoLocal": train_no,
+            "TrainName": "EIP",
+            "StartStation": "Gliwice",
+            "EndStation": "Kraków Główny",
+            "Vehicles": ["Pendolino/ED250-018 Variant"],
+            "ServerCode": "pl1",
+            "TrainData": {
+                "ControlledBySteamID": driver,
+                "Latititute": position.0,
+                "Longitute": position.1,
+                "Velocity": 90.0,
+                "SignalInFront": null,
+                "DistanceToSignalInFront": 0.0,
+                "VDDelayedTimetableIndex": index,
+            },
+            "Type": if driver.is_some() { "user" } else { "bot" },
+        })
+    }
+
+    fn trains(trains: Vec<Value>) -> Value {
+        json!({ "result": true, "data": trains })
+    }
+
+    /// A stop at `index`, `time` being the scheduled arrival and departure.
+    fn stop(index: u64, name: &str, time: &str, planned_stop: i64) -> Value {
+        let time = format!("2024-05-01T{time}:00.000Z");
+        json!({
+            "indexOfPoint": index,
+            "nameOfPoint": name,
+            "line": 1,
+            "arrivalTime": null,
+            "departureTime": null,
+            "scheduledArrivalObject": time,
+            "scheduledDepartureObject": time,
+            "platform": if planned_stop == 0 { None } else { Some("II") },
+            "track": if planned_stop == 0 { None } else { Some(2) },
+            "plannedStop": planned_stop,
+        })
+    }
+
+    /// Gliwice -> Katowice -> Sosnowiec Główny, stopping at Katowice.
+    fn timetable() -> Value {
+        json!([
+            stop(11, "Gliwice", "09:40", 1),
+            stop(12, "Katowice", "10:00", 1),
+            stop(13, "Sosnowiec Główny", "10:09", 0),
+        ])
+    }
+
+    /// The board of Katowice on pl1 after one refresh.
+    async fn board(server: &MockServer, trains: Value) -> State {
+        let mut state = station_selection(server).await;
+        mount(server, "/trains-open", trains).await;
+        state.select();
+        assert_eq!(
+            state.selected_station.as_ref().map(|s| s.name.as_str()),
+            Some("Katowice")
+        );
+        state.refresh_data().await.unwrap();
+        state
+    }
+
+    const GLIWICE: (f32, f32) = (50.2945, 18.6714);
+
+    fn parse<T: serde::de::DeserializeOwned>(value: Value) -> T {
+        serde_json::from_value(value).unwrap()
+    }
+
+    /// The events of `station` for a train at the start of `timetable`.
+    fn events_at(timetable: Value, station: &str) -> Vec<Event> {
+        let train: Train = parse(train("40150", GLIWICE, -1, None));
+        let timetable: Vec<StopDescription> = parse(timetable);
+        station_events(
+            &train,
+            &timetable,
+            0,
+            station,
+            &HashMap::new(),
+            &mut PlatformTracker::default(),
+            &Config::default(),
+        )
+    }
+
+    #[tokio::test]
+    async fn known_players_are_not_looked_up_again() {
+        let server = MockServer::start().await;
+        let mut state = mock_state(&server).await;
+        mount(&server, "/stations-open", stations()).await;
+        Mock::given(path(format!("/users-open/{DISPATCHER}")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(users(&[DISPATCHER])))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        state.select();
+        state.refresh_data().await.unwrap();
+        state.refresh_data().await.unwrap();
+        assert_eq!(
+            state.get_player_name(Some(&DISPATCHER.to_string())),
+            Some(&format!("player {DISPATCHER}"))
+        );
+    }
+
+    fn at(time: &str) -> DateTime<Utc> {
+        format!("2024-05-01T{time}:00Z").parse().unwrap()
+    }
+
+    fn event(name: &str, ty: EventType, planned: &str, actual: Option<&str>) -> Event {
+        Event {
+            name: name.to_string(),
+            station: String::from("Katowice"),
+            time: actual.map(at),
+            planned_time: at(planned),
+            ty,
+            player: false,
+            driver: None,
+            train_no: name.to_string(),
+            category: TrainCategory::Other,
+            start: String::from("A"),
+            end: String::from("B"),
+            visit: None,
+            platform: None,
+            platform_change: None,
+            line: 1,
+            eta: None,
+            prev: String::from("ORIGIN"),
+            next: String::from("TERM"),
+        }
+    }
+
+    #[test]
+    fn on_time_events_show_the_time_alone() {
+        let config = Config::default();
+        let e = event("1", EventType::Entering, "10:00", Some("10:00"));
+        assert_eq!(e.get_time(&config), config.format_time(&at("10:00")));
+        let e = event("1", EventType::Entering, "10:00", None);
+        assert_eq!(e.get_time(&config), config.format_time(&at("10:00")));
+    }
+
+    #[test]
+    fn late_and_early_events_show_their_delay() {
+        let config = Config::default();
+        let late = event("1", EventType::Entering, "10:00", Some("10:03"));
+        let time = config.format_time(&at("10:03"));
+        assert_eq!(late.get_time(&config), format!("{time} (+3)"));
+        let early = event("1", EventType::Entering, "10:00", Some("09:59"));
+        let time = config.format_time(&at("09:59"));
+        assert_eq!(early.get_time(&config), format!("{time} (-1)"));
+    }
+
+    #[test]
+    fn the_delay_suffix_is_the_same_on_both_clocks() {
+        let late = event("1", EventType::Entering, "14:00", Some("14:03"));
+        let config = |time_format| Config {
+            time_format,
+            timezone: Some(chrono_tz::UTC),
+            ..Config::default()
+        };
+        assert_eq!(late.get_time(&config(TimeFormat::H24)), "14:03 (+3)");
+        assert_eq!(late.get_time(&config(TimeFormat::H12)), "02:03 PM (+3)");
+    }
+
+    #[test]
+    fn delay_minutes_is_signed_and_unknown_without_an_actual_time() {
+        let delay = |actual| event("1", EventType::Entering, "10:00", actual).delay_minutes();
+        assert_eq!(delay(Some("10:00")), Some(0));
+        assert_eq!(delay(Some("10:07")), Some(7));
+        assert_eq!(delay(Some("09:58")), Some(-2));
+        assert_eq!(delay(None), None);
+    }
+
+    #[test]
+    fn events_with_actual_times_compare_by_them() {
+        let late = event("1", EventType::Entering, "10:00", Some("10:10"));
+        let early = event("2", EventType::Entering, "10:05", Some("10:06"));
+        assert!(early < late);
+    }
+
+    #[test]
+    fn events_without_actual_times_compare_by_planned_times() {
+        let first = event("1", EventType::Entering, "10:00", None);
+        let second = event("2", EventType::Entering, "10:05", None);
+        assert!(first < second);
+    }
+
+    #[test]
+    fn events_at_the_same_time_are_ordered_by_type() {
+        let mut events = vec![
+            event("1", EventType::Departing, "10:00", None),
+            event("1", EventType::Passing, "10:00", None),
+            event("1", EventType::Entering, "10:00", None),
+        ];
+        events.sort();
+        let types: Vec<EventType> = events.into_iter().map(|e| e.ty).collect();
+        assert_eq!(
+            types,
+            [
+                EventType::Passing,
+                EventType::Entering,
+                EventType::Departing
+            ]
+        );
+    }
+
+    #[test]
+    fn calls_of_a_train_at_two_stations_are_not_equal() {
+        let a = event("1", EventType::Entering, "10:00", None);
+        let mut b = a.clone();
+        b.station = String::from("Sosnowiec");
+        assert_ne!(a.cmp(&b), std::cmp::Ordering::Equal);
+        assert_ne!(a, b);
+
+        b.station = a.station.clone();
+        b.eta = Some(at("10:02"));
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn an_actual_time_moves_an_event_behind_a_planned_one() {
+        let late = event("1", EventType::Entering, "10:00", Some("10:10"));
+        let planned = event("2", EventType::Entering, "10:05", None);
+        assert!(planned < late);
+    }
+
+    #[test]
+    fn platform_tracker_forgets_records_missing_from_a_refresh() {
+        let window = Duration::from_secs(60);
+        let mut tracker = PlatformTracker::default();
+        let platform = Some(String::from("I/1"));
+        tracker.update("1", "A", platform.as_ref(), window);
+        tracker.update("2", "A", platform.as_ref(), window);
+        tracker.prune();
+        assert_eq!(tracker.platforms.len(), 2);
+
+        tracker.update("1", "A", platform.as_ref(), window);
+        tracker.prune();
+        assert_eq!(
+            tracker.platforms.keys().collect::<Vec<_>>(),
+            [&(String::from("1"), String::from("A"))]
+        );
+    }
+
+    #[tokio::test]
+    async fn j_moves_the_selection_down() {
+        let server = MockServer::start().await;
+        let mut state = mock_state(&server).await;
+        assert_eq!(state.server_index, 0);
+        assert_eq!(state.key_pressed(KeyCode::Char('j')), (false, true));
+        assert_eq!(state.server_index, 1);
+    }
+
+    #[tokio::test]
+    async fn a_board_without_a_station_falls_back_to_the_station_selection() {
+        let server = MockServer::start().await;
+        let mut state = station_selection(&server).await;
+        state.step = Step::EDR;
+        assert!(state.selected_station.is_none());
+
+        state.refresh_data().await.unwrap();
+        assert!(matches!(state.step, Step::StationSelection));
+        assert_eq!(state.stations.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn the_server_selection_loads_the_servers() {
+        let server = MockServer::start().await;
+        let mut state = mock_state(&server).await;
+        state.refresh_data().await.unwrap();
+        let codes: Vec<&str> = state
+            .servers
+            .iter()
+            .map(|s| s.server_code.as_str())
+            .collect();
+        assert_eq!(codes, ["pl1", "en1"]);
+        assert!(state.servers[0].is_active && !state.servers[1].is_active);
+    }
+
+    #[tokio::test]
+    async fn the_station_selection_loads_the_stations_and_dispatchers() {
+        let server = MockServer::start().await;
+        let state = station_selection(&server).await;
+        assert!(matches!(state.step, Step::StationSelection));
+        let names: Vec<&str> = state.stations.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["Katowice", "Sosnowiec Główny"]);
+        assert_eq!(
+            state.get_player_name(Some(&DISPATCHER.to_string())),
+            Some(&format!("player {DISPATCHER}"))
+        );
+    }
+
+    #[tokio::test]
+    async fn the_board_lists_the_calls_of_the_timetables() {
+        let server = MockServer::start().await;
+        mount(&server, "/train/pl1/40150", timetable()).await;
+        let state = board(&server, trains(vec![train("40150", GLIWICE, 11, None)])).await;
+
+        let events: Vec<(&str, EventType, Option<&str>)> = state
+            .visible_events()
+            .into_iter()
+            .map(|e| (e.train_no.as_str(), e.ty.clone(), e.platform.as_deref()))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                ("40150", EventType::Entering, Some("II/2")),
+                ("40150", EventType::Departing, Some("II/2")),
+            ]
+        );
+        let event = &state.visible_events()[0];
+        assert_eq!(event.planned_time, at("10:00"));
+        assert_eq!(
+            (event.prev.as_str(), event.next.as_str()),
+            ("Gliwice/L.1", "Sosnowiec Główny/L.1")
+        );
+    }
+
+    #[tokio::test]
+    async fn malformed_json_is_an_error() {
+        let server = MockServer::start().await;
+        let mut state = mock_state(&server).await;
+        Mock::given(path("/stations-open"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_string("{\"result\": true, \"data\": ["),
+            )
+            .mount(&server)
+            .await;
+        state.select();
+        assert!(state.refresh_data().await.is_err());
+        assert!(state.stations.is_empty());
+    }
+
+    #[test]
+    fn each_visit_of_a_station_gets_its_events() {
+        let timetable = json!([
+            stop(10, "Katowice", "10:00", 1),
+            stop(11, "Sosnowiec Główny", "10:09", 1),
+            stop(12, "Katowice", "10:20", 1),
+        ]);
+        let events: Vec<(Option<usize>, EventType, DateTime<Utc>)> =
+            events_at(timetable, "Katowice")
+                .into_iter()
+                .map(|e| (e.visit, e.ty, e.planned_time))
+                .collect();
+        assert_eq!(
+            events,
+            [
+                (Some(1), EventType::Entering, at("10:00")),
+                (Some(1), EventType::Departing, at("10:00")),
+                (Some(2), EventType::Entering, at("10:20")),
+                (Some(2), EventType::Departing, at("10:20")),
+            ]
+        );
+    }
+
+    const NAVIGATION: [KeyCode; 8] = [
+        KeyCode::Down,
+        KeyCode::Up,
+        KeyCode::PageDown,
+        KeyCode::PageUp,
+        KeyCode::Home,
+        KeyCode::End,
+        KeyCode::Char('j'),
+        KeyCode::Enter,
+    ];
+
+    #[tokio::test]
+    async fn an_empty_server_list_can_be_navigated() {
+        let server = MockServer::start().await;
+        let mut state = state_with_servers(&server, json!({ "result": true, "data": [] })).await;
+        for key in NAVIGATION {
+            assert_eq!(state.key_pressed(key), (false, false), "{key:?}");
+            assert_eq!(state.server_index, 0);
+        }
+        assert!(matches!(state.step, Step::ServerSelection));
+    }
+
+    #[tokio::test]
+    async fn an_empty_station_list_can_be_navigated() {
+        let server = MockServer::start().await;
+        let mut state = mock_state(&server).await;
+        mount(
+            &server,
+            "/stations-open",
+            json!({ "result": true, "data": [] }),
+        )
+        .await;
+        state.select();
+        state.refresh_data().await.unwrap();
+        for key in NAVIGATION {
+            assert_eq!(state.key_pressed(key), (false, false), "{key:?}");
+            assert_eq!(state.station_index, 0);
+        }
+        assert!(matches!(state.step, Step::StationSelection));
+    }
+
+    #[test]
+    fn the_first_and_last_stops_are_marked_origin_and_terminus() {
+        let timetable = json!([
+            stop(10, "Katowice", "10:00", 1),
+            stop(11, "Sosnowiec Główny", "10:09", 1),
+        ]);
+        let origin = events_at(timetable.clone(), "Katowice");
+        assert!(origin.iter().all(|e| e.prev == "ORIGIN"));
+        assert!(origin.iter().all(|e| e.next == "Sosnowiec Główny/L.1"));
+
+        let terminus = events_at(timetable, "Sosnowiec Główny");
+        assert!(terminus.iter().all(|e| e.prev == "Katowice/L.1"));
+        assert!(terminus.iter().all(|e| e.next == "TERM"));
+        assert_eq!(terminus.len(), 2);
+    }
+
+    #[test]
+    fn the_timetable_index_wins_over_the_nearest_station() {
+        let timetable: Vec<StopDescription> = parse(json!([
+            stop(11, "Gliwice", "09:40", 1),
+            stop(12, "Katowice", "10:00", 1),
+            stop(13, "Sosnowiec Główny", "10:09", 0),
+        ]));
+        let mut train: Train = parse(train("40150", GLIWICE, 12, None));
+        train.loc = Some(String::from("Gliwice"));
+        // Index 12 is the second entry, not the thirteenth.
+        assert_eq!(train_position(&train, &timetable), Some(1));
+
+        // Unknown or absent indexes fall back to the nearest station.
+        train.train_data.vddelayed_timetable_index = -1;
+        assert_eq!(train_position(&train, &timetable), Some(0));
+        train.train_data.vddelayed_timetable_index = 40;
+        assert_eq!(train_position(&train, &timetable), Some(0));
+    }
+
+    #[tokio::test]
+    async fn trains_without_coordinates_are_left_off_the_board() {
+        let server = MockServer::start().await;
+        mount(&server, "/train/pl1/40150", timetable()).await;
+        Mock::given(path("/train/pl1/40152"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(timetable()))
+            .expect(0)
+            .mount(&server)
+            .await;
+        // NaN is sent as null, like the coordinates of trains still spawning.
+        let nowhere = (f32::NAN, f32::NAN);
+        let state = board(
+            &server,
+            trains(vec![
+                train("40150", GLIWICE, 11, None),
+                train("40152", nowhere, 11, None),
+            ]),
+        )
+        .await;
+
+        assert!(state.visible_events().iter().all(|e| e.train_no == "40150"));
+        assert_eq!(state.visible_events().len(), 2);
+        assert_eq!(state.unlocated_trains, 1);
+    }
+
+    #[tokio::test]
+    async fn an_inactive_server_cannot_be_selected() {
+        let server = MockServer::start().await;
+        let mut state = mock_state(&server).await;
+        state.server_index = 1;
+
+        assert_eq!(state.key_pressed(KeyCode::Enter), (false, true));
+        assert!(matches!(state.step, Step::ServerSelection));
+        assert!(state.selected_server.is_empty());
+        assert_eq!(state.status.as_deref(), Some("en1 is inactive"));
+    }
+
+    #[tokio::test]
+    async fn the_selection_wraps_around_unless_disabled() {
+        let server = MockServer::start().await;
+        let mut state = mock_state(&server).await;
+        state.key_pressed(KeyCode::Up);
+        assert_eq!(state.server_index, 1);
+        state.key_pressed(KeyCode::Down);
+        assert_eq!(state.server_index, 0);
+
+        state.config.wrap_navigation = false;
+        state.key_pressed(KeyCode::Up);
+        assert_eq!(state.server_index, 0);
+        state.key_pressed(KeyCode::Down);
+        state.key_pressed(KeyCode::Down);
+        assert_eq!(state.server_index, 1);
+    }
+
+    #[tokio::test]
+    async fn dispatchers_and_drivers_are_resolved_in_one_request() {
+        const DRIVER: &str = "76561198000000002";
+        let server = MockServer::start().await;
+        let mut state = mock_state(&server).await;
+        mount(&server, "/stations-open", stations()).await;
+
+        // users-open is not mocked yet: the lookup fails and the list loads.
+        state.select();
+        state.refresh_data().await.unwrap();
+        assert_eq!(state.stations.len(), 2);
+        assert!(state.player_names.is_empty());
+
+        mount(&server, "/train/pl1/40150", timetable()).await;
+        mount(
+            &server,
+            "/trains-open",
+            trains(vec![train("40150", GLIWICE, 11, Some(DRIVER))]),
+        )
+        .await;
+        Mock::given(path(format!("/users-open/{DISPATCHER},{DRIVER}")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(users(&[DISPATCHER, DRIVER])))
+            .expect(1)
+            .mount(&server)
+            .await;
+        state.select();
+        state.refresh_data().await.unwrap();
+
+        assert_eq!(state.visible_events().len(), 2);
+        for id in [DISPATCHER, DRIVER] {
+            assert_eq!(
+                state.get_player_name(Some(&id.to_string())),
+                Some(&format!("player {id}"))
+            );
+        }
+    }
+
+    #[test]
+    fn train_names_map_to_their_category() {
+        let cases = [
+            ("EIJ", TrainCategory::Express),
+            ("EIP", TrainCategory::Express),
+            ("EIC", TrainCategory::Express),
+            ("EC", TrainCategory::Express),
+            ("MPE", TrainCategory::InterCity),
+            ("MOJ", TrainCategory::InterCity),
+            ("ROJ", TrainCategory::Regional),
+            ("RPJ", TrainCategory::Regional),
+            ("TME", TrainCategory::Freight),
+            ("TKS", TrainCategory::Freight),
+            ("LTE", TrainCategory::Service),
+            ("PWJ", TrainCategory::Service),
+            (" roj", TrainCategory::Regional),
+            ("", TrainCategory::Other),
+            ("ZXC", TrainCategory::Other),
+        ];
+        for (name, category) in cases {
+            assert_eq!(TrainCategory::parse(name), category, "{name:?}");
+        }
+        // Every express service shares one tag, not the name of one of them.
+        assert_eq!(TrainCategory::parse("EIC").tag(), "EXP");
+        assert_eq!(TrainCategory::parse("EC").tag(), "EXP");
+    }
+
+    #[tokio::test]
+    async fn a_timetable_served_as_html_only_drops_that_train() {
+        let server = MockServer::start().await;
+        mount(&server, "/train/pl1/40150", timetable()).await;
+        Mock::given(path("/train/pl1/40152"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("content-type", "text/html")
+                    .set_body_string("<html><body>502 Bad Gateway</body></html>"),
+            )
+            .mount(&server)
+            .await;
+        let state = board(
+            &server,
+            trains(vec![
+                train("40150", GLIWICE, 11, None),
+                train("40152", GLIWICE, 11, None),
+            ]),
+        )
+        .await;
+
+        assert_eq!(state.visible_events().len(), 2);
+        assert!(state.visible_events().iter().all(|e| e.train_no == "40150"));
+        assert_eq!(state.status.as_deref(), Some("1 trains failed to load"));
+    }
+
+    #[tokio::test]
+    async fn each_sort_mode_orders_the_board() {
+        let server = MockServer::start().await;
+        let mut state = mock_state(&server).await;
+        state.tabs = vec![String::from("Katowice")];
+        state.boards = HashMap::from([(
+            String::from("Katowice"),
+            vec![
+                event("A", EventType::Entering, "10:00", Some("10:05")),
+                event("B", EventType::Entering, "09:50", Some("09:48")),
+                event("C", EventType::Entering, "10:10", None),
+                event("D", EventType::Entering, "09:55", Some("10:05")),
+                event("E", EventType::Entering, "09:30", None),
+            ],
+        )]);
+
+        for (sort, order) in [
+            (SortMode::Time, ["E", "B", "A", "D", "C"]),
+            // Events without a delay go last, in time order.
+            (SortMode::Delay, ["D", "A", "B", "E", "C"]),
+            (SortMode::Name, ["A", "B", "C", "D", "E"]),
+        ] {
+            state.sort = sort;
+            state.show_board();
+            let names: Vec<&str> = state
+                .visible_events()
+                .iter()
+                .map(|e| e.name.as_str())
+                .collect();
+            assert_eq!(names, order, "{sort:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn a_session_is_resumed_on_its_board() {
+        let server = MockServer::start().await;
+        let mut state = mock_state(&server).await;
+        mount(&server, "/stations-open", stations()).await;
+        state
+            .resume(Session {
+                server: Some(String::from("PL1")),
+                station: Some(String::from("ko")),
+            })
+            .await;
+        assert!(matches!(state.step, Step::EDR));
+        assert_eq!(state.tabs, ["Katowice"]);
+    }
+
+    #[tokio::test]
+    async fn an_unreachable_api_does_not_stop_the_resume() {
+        let server = MockServer::start().await;
+        let mut state = mock_state(&server).await;
+        // stations-open is not mocked and answers 404.
+        state
+            .resume(Session {
+                server: Some(String::from("pl1")),
+                station: Some(String::from("Katowice")),
+            })
+            .await;
+        assert!(matches!(state.step, Step::StationSelection));
+        assert!(state.refresh_error.is_some());
+        assert_eq!(
+            state.status.as_deref(),
+            Some("Station Katowice could not be restored")
+        );
+    }
+
+    #[tokio::test]
+    async fn exported_columns_follow_the_screen() {
+        let server = MockServer::start().await;
+        let mut state = mock_state(&server).await;
+        state.config.columns = vec![
+            Column::Marker,
+            Column::Train,
+            Column::Station,
+            Column::Time,
+            Column::Delay,
+            Column::Platform,
+        ];
+        let e = event("EIP 1", EventType::Entering, "10:00", Some("10:03"));
+        let row = |state: &State| {
+            let layout = state.board_layout();
+            (
+                layout.shown(state.board_header()),
+                layout.shown(state.board_columns(&e)),
+            )
+        };
+
+        // The station only shows on combined boards.
+        let (header, columns) = row(&state);
+        assert_eq!(header, ["", "Train", "Time", "Delay", "Platform"]);
+        assert_eq!(columns[2], state.config.format_time(&at("10:03")) + " (+3)");
+
+        state.compact_board = true;
+        state.split_times = true;
+        let (header, columns) = row(&state);
+        assert_eq!(header, ["", "Train", "Sched", "Act"]);
+        assert_eq!(
+            columns[2..],
+            [
+                state.config.format_time(&at("10:00")),
+                state.config.format_time(&at("10:03")),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn watched_trains_go_first_within_their_line() {
+        let server = MockServer::start().await;
+        let mut state = mock_state(&server).await;
+        let on_line = |name: &str, planned: &str, line: i64| Event {
+            line,
+            ..event(name, EventType::Entering, planned, None)
+        };
+        state.tabs = vec![String::from("Katowice")];
+        state.boards = HashMap::from([(
+            String::from("Katowice"),
+            vec![
+                on_line("A", "10:00", 1),
+                on_line("B", "10:05", 2),
+                on_line("C", "10:10", 1),
+                on_line("D", "10:15", 2),
+            ],
+        )]);
+        state
+            .watchlist
+            .insert(String::new(), vec![String::from("D")]);
+
+        let order = |state: &State| -> Vec<String> {
+            state
+                .visible_events()
+                .iter()
+                .map(|e| e.name.clone())
+                .collect()
+        };
+        state.show_board();
+        assert_eq!(order(&state), ["D", "A", "B", "C"]);
+        state.group_by_line = true;
+        state.show_board();
+        assert_eq!(order(&state), ["A", "C", "D", "B"]);
+    }
+
+    #[tokio::test]
+    async fn the_time_window_counts_past_minutes_positive() {
+        let server = MockServer::start().await;
+        let mut state = mock_state(&server).await;
+        let in_mins = |name: &str, mins: i64| Event {
+            planned_time: Utc::now() + chrono::Duration::minutes(mins),
+            ..event(name, EventType::Entering, "10:00", None)
+        };
+        state.events = vec![
+            in_mins("past", -20),
+            in_mins("recent", -10),
+            in_mins("soon", 50),
+            in_mins("later", 70),
+        ];
+        state.time_window = Some((15, 60));
+        let names: Vec<&str> = state
+            .visible_events()
+            .iter()
+            .map(|e| e.name.as_str())
+            .collect();
+        assert_eq!(names, ["recent", "soon"]);
+    }
 }
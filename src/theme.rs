@@ -0,0 +1,132 @@
+use serde::Deserialize;
+use tui::style::{Color, Modifier, Style};
+
+/// Themes selectable with `theme` in the config.
+#[derive(Debug, Deserialize, Clone, Copy, Default)]
+pub enum ThemeName {
+    /// Colored rows on the terminal palette.
+    #[default]
+    Default,
+    /// Bright colors and no underlined rows, for low-vision users and washed
+    /// out palettes.
+    HighContrast,
+    /// Modifiers only, for terminals without colors and colorblind users.
+    Mono,
+}
+
+/// Every style the screens are drawn with.
+#[derive(Debug, Clone)]
+pub struct Theme {
+    /// Selected row of lists, tables and tabs.
+    pub selected: Style,
+    pub footer: Style,
+    /// Server offline banner.
+    pub banner: Style,
+    /// Key names, titles, the stop of the board in timetables, staffed stations.
+    pub emphasis: Style,
+    /// Placeholders of empty lists.
+    pub muted: Style,
+    pub inactive_server: Style,
+    /// Base of every board row, patched by the event type.
+    pub event: Style,
+    pub passing: Style,
+    pub entering: Style,
+    pub departing: Style,
+    pub player: Style,
+    pub late: Style,
+    pub delayed: Style,
+    pub platform_change: Style,
+    /// Patched over the rows of watched trains.
+    pub watched: Style,
+    pub health_ok: Style,
+    pub health_slow: Style,
+    pub health_error: Style,
+    /// Whether the colors set in the config, e.g. for dispatchers, are used.
+    colors: bool,
+}
+
+impl Theme {
+    /// `alert_color` is the configured color of the late events, ignored by
+    /// the monochrome theme.
+    pub fn new(name: ThemeName, alert_color: Color) -> Theme {
+        let bold = Style::default().add_modifier(Modifier::BOLD);
+        match name {
+            ThemeName::Default => Theme {
+                selected: Style::default().add_modifier(Modifier::REVERSED),
+                footer: Style::default().add_modifier(Modifier::REVERSED),
+                banner: bold.fg(Color::White).bg(Color::Red),
+                emphasis: bold,
+                muted: Style::default().fg(Color::DarkGray),
+                inactive_server: Style::default().add_modifier(Modifier::CROSSED_OUT),
+                event: Style::default().add_modifier(Modifier::UNDERLINED),
+                passing: Style::default().fg(Color::DarkGray),
+                entering: Style::default().fg(Color::Green),
+                departing: Style::default().fg(Color::Red),
+                player: bold,
+                late: bold.fg(alert_color),
+                delayed: Style::default().fg(Color::Red),
+                platform_change: bold.fg(Color::Yellow),
+                watched: Style::default().bg(Color::Blue),
+                health_ok: Style::default().fg(Color::Green),
+                health_slow: Style::default().fg(Color::Yellow),
+                health_error: Style::default().fg(Color::Red),
+                colors: true,
+            },
+            ThemeName::HighContrast => Theme {
+                selected: bold.fg(Color::Black).bg(Color::Yellow),
+                footer: Style::default().fg(Color::Black).bg(Color::White),
+                banner: bold.fg(Color::White).bg(Color::Red),
+                emphasis: bold,
+                muted: Style::default().fg(Color::Gray),
+                inactive_server: Style::default()
+                    .fg(Color::Gray)
+                    .add_modifier(Modifier::CROSSED_OUT),
+                event: Style::default(),
+                passing: Style::default().fg(Color::White),
+                entering: Style::default().fg(Color::LightGreen),
+                departing: Style::default().fg(Color::LightRed),
+                player: bold,
+                late: bold.fg(alert_color),
+                delayed: Style::default().fg(Color::LightRed),
+                platform_change: bold
+                    .fg(Color::LightYellow)
+                    .add_modifier(Modifier::UNDERLINED),
+                watched: Style::default().fg(Color::White).bg(Color::Blue),
+                health_ok: Style::default().fg(Color::LightGreen),
+                health_slow: Style::default().fg(Color::LightYellow),
+                health_error: Style::default().fg(Color::LightRed),
+                colors: true,
+            },
+            ThemeName::Mono => Theme {
+                selected: Style::default().add_modifier(Modifier::REVERSED),
+                footer: Style::default().add_modifier(Modifier::REVERSED),
+                banner: bold.add_modifier(Modifier::REVERSED),
+                emphasis: bold,
+                muted: Style::default().add_modifier(Modifier::DIM),
+                inactive_server: Style::default()
+                    .add_modifier(Modifier::CROSSED_OUT | Modifier::DIM),
+                event: Style::default(),
+                passing: Style::default().add_modifier(Modifier::DIM),
+                entering: Style::default(),
+                departing: Style::default(),
+                player: bold,
+                late: bold.add_modifier(Modifier::UNDERLINED),
+                delayed: bold,
+                platform_change: bold.add_modifier(Modifier::UNDERLINED),
+                watched: Style::default().add_modifier(Modifier::ITALIC),
+                health_ok: Style::default(),
+                health_slow: bold,
+                health_error: bold.add_modifier(Modifier::SLOW_BLINK),
+                colors: false,
+            },
+        }
+    }
+
+    /// A configured color, dropped by the themes without colors.
+    pub fn color(&self, color: Option<Color>) -> Style {
+        match color {
+            Some(color) if self.colors => Style::default().fg(color),
+            _ => Style::default(),
+        }
+    }
+}